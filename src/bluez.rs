@@ -0,0 +1,517 @@
+use std::{
+    fs::{copy, create_dir_all, read_dir, read_to_string, File},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use inquire::MultiSelect;
+use log::{debug, error, info, warn};
+
+use crate::{
+    bt_device::{
+        linux_bt_device,
+        uni_bt_device::{AddressType, UniBtDevice},
+    },
+    confirm_list, freshness, identity,
+    ledger::{self, Direction},
+    names::NameIndex,
+    pipeline::{self, ConflictPolicy, KeySink, KeySource, Policy, SourceKeys, System},
+    report,
+    utils::{format_time, is_valid_linux_address, parse_time},
+    CustomResult, Interaction, LINUX_BACKUP_DIR, LINUX_BT_DIR, LINUX_OS_RELEASE,
+};
+
+/// Bluetooth directory of a linux installation, `/` for the running system
+pub struct BluezDir<'a> {
+    pub root: PathBuf,
+    pub interaction: &'a Interaction,
+}
+
+impl KeySource for BluezDir<'_> {
+    fn read(&mut self) -> CustomResult<SourceKeys> {
+        let devices = get_linux_devices(&self.root)?
+            .into_iter()
+            .filter_map(|(adapter, device, linux_dev)| {
+                let parent_address =
+                    linux_bt_device::BtAddress(adapter).to_uni(AddressType::Public)?;
+                let address =
+                    linux_bt_device::BtAddress(device).to_uni(linux_dev.address_type())?;
+                Some(linux_dev.uni_device(parent_address, address))
+            })
+            .collect();
+
+        Ok(SourceKeys {
+            source: self.root.display().to_string(),
+            system: System::Linux,
+            devices,
+            adapters: identity::read(&self.root)?,
+        })
+    }
+}
+
+impl KeySink for BluezDir<'_> {
+    fn system(&self) -> System {
+        System::Linux
+    }
+
+    fn target(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    /// Asks once for the whole installation, devices are then updated one by one
+    fn write(&mut self, keys: &SourceKeys, policy: &Policy) -> CustomResult<report::RootReport> {
+        let root = &self.root;
+        let names = NameIndex::load(root);
+        let labels: Vec<_> = keys.devices.iter().map(|d| names.label(d)).collect();
+        let question = format!(
+            "sync {} device(s) into {}?",
+            keys.devices.len(),
+            root.display()
+        );
+
+        if confirm_list(&question, &labels, self.interaction)? {
+            Ok(update_linux_devices(
+                keys,
+                root,
+                &names,
+                policy,
+                self.interaction,
+            ))
+        } else {
+            warn!("skipped {:?}", root);
+            Ok(skipped_root(&keys.devices, root, &names))
+        }
+    }
+}
+
+/// Paired devices found in linux as `(adapter, device, info)`, e.g.
+/// `("C0:FB:F9:60:1C:13", "4C:87:5D:26:DC:9F", ...)`
+pub fn get_linux_devices(
+    root: &Path,
+) -> CustomResult<Vec<(String, String, linux_bt_device::BtDevice)>> {
+    let mut devices = vec![];
+
+    for adapter in read_dir(root.join(LINUX_BT_DIR)).map_err(|e| e.into())? {
+        let adapter = adapter.map_err(|e| e.into())?;
+        let adapter_name = adapter.file_name().to_string_lossy().to_string();
+        if !is_valid_linux_address(&adapter_name) || !adapter.path().is_dir() {
+            continue;
+        }
+
+        for device in read_dir(adapter.path()).map_err(|e| e.into())? {
+            let device = device.map_err(|e| e.into())?;
+            let device_name = device.file_name().to_string_lossy().to_string();
+            let info_path = device.path().join("info");
+            if !is_valid_linux_address(&device_name) || !info_path.exists() {
+                continue;
+            }
+
+            let info_str = read_to_string(&info_path).map_err(|e| e.into())?;
+            match serde_ini::from_str(&info_str) {
+                Ok(linux_dev) => devices.push((adapter_name.clone(), device_name, linux_dev)),
+                Err(e) => warn!("can't parse {:?}: {:?}", info_path, e),
+            }
+        }
+    }
+
+    debug!("found {} linux device(s)", devices.len());
+    Ok(devices)
+}
+
+/// Applies keys of source devices to linux installation at `root`, every device
+/// ends up updated, unchanged, skipped or failed. Changes go to the ledger of `root`.
+/// Adapters with LE devices also get the local IRK of the source
+fn update_linux_devices(
+    keys: &SourceKeys,
+    root: &Path,
+    names: &NameIndex,
+    policy: &Policy,
+    interaction: &Interaction,
+) -> report::RootReport {
+    let win_devices = &keys.devices;
+    let source = &keys.source;
+    let direction = Direction {
+        from: keys.system,
+        to: System::Linux,
+    };
+    let now = SystemTime::now();
+    let started = now
+        .duration_since(UNIX_EPOCH)
+        .expect("clock is after 1970")
+        .as_secs();
+    let backup_dir = root.join(LINUX_BACKUP_DIR).join(started.to_string());
+    let mut ledger_entries = vec![];
+    let ledger = ledger::read(root).unwrap_or_else(|e| {
+        warn!("can't read the ledger of {:?}: {:?}", root, e);
+        vec![]
+    });
+
+    let devices: Vec<_> = win_devices
+        .iter()
+        .map(|d| {
+            let adapter = linux_bt_device::BtAddress::from(d.parent_address.clone()).0;
+            let address = linux_bt_device::BtAddress::from(d.address.clone()).0;
+            let d_path = root.join(LINUX_BT_DIR).join(&adapter).join(&address);
+            let d_backup_dir = backup_dir.join(&adapter).join(&address);
+
+            let (outcome, decided_by) = if !d_path.exists() {
+                warn!(
+                    device = address.as_str(), adapter = adapter.as_str(), action = "skipped";
+                    "device {} from {} is not connected in linux {:?}",
+                    names.label(d),
+                    keys.system,
+                    root
+                );
+                let outcome = report::Outcome::Skipped {
+                    reason: "not paired in linux".to_string(),
+                };
+                (outcome, None)
+            } else {
+                let last_sync = ledger
+                    .iter()
+                    .filter(|e| e.adapter == adapter && e.device == address)
+                    .filter_map(|e| parse_time(&e.time))
+                    .max();
+                let conflict = policy.conflict_for(&address);
+                let skip = conflict == ConflictPolicy::Skip;
+                let mut decided = false;
+                let may_overwrite = |d: &UniBtDevice| {
+                    decided = true;
+                    let freshness = freshness::compare(d, root, &adapter, &address, last_sync);
+                    pipeline::resolve_conflict(
+                        &names.label(d),
+                        conflict,
+                        freshness,
+                        Direction {
+                            from: keys.system,
+                            to: System::Linux,
+                        },
+                        interaction,
+                    )
+                };
+
+                let outcome =
+                    match update_linux_device(d, &d_path, &d_backup_dir, policy, skip, may_overwrite) {
+                        Ok(DeviceUpdate::Written(sections)) => {
+                            info!(
+                                device = address.as_str(), adapter = adapter.as_str(), action = "updated";
+                                "updated {:?} device", d_path
+                            );
+                            ledger_entries.push(ledger::LedgerEntry {
+                                time: format_time(now),
+                                direction,
+                                source: source.to_string(),
+                                adapter: adapter.clone(),
+                                device: address.clone(),
+                                name: names.device_name(d),
+                                sections,
+                            });
+                            report::Outcome::Updated
+                        }
+                        Ok(DeviceUpdate::Unchanged) => report::Outcome::Unchanged,
+                        Ok(DeviceUpdate::Skipped) => {
+                            info!(
+                                device = address.as_str(), adapter = adapter.as_str(), action = "skipped";
+                                "left {} alone because of {}", names.label(d), conflict
+                            );
+                            decided = true;
+                            report::Outcome::Skipped {
+                                reason: pipeline::kept_reason(conflict, System::Linux),
+                            }
+                        }
+                        Ok(DeviceUpdate::Kept) => {
+                            info!(
+                                device = address.as_str(), adapter = adapter.as_str(), action = "kept";
+                                "kept the linux pairing of {} because of {}", names.label(d), conflict
+                            );
+                            report::Outcome::Skipped {
+                                reason: pipeline::kept_reason(conflict, System::Linux),
+                            }
+                        }
+                        Err(e) => {
+                            error!(
+                                device = address.as_str(), adapter = adapter.as_str(), action = "failed";
+                                "can't update {}: {:?}", names.label(d), e
+                            );
+                            report::Outcome::Failed {
+                                error: format!("{:?}", e),
+                            }
+                        }
+                    };
+                (outcome, Some(conflict).filter(|_| decided))
+            };
+
+            report::DeviceReport {
+                adapter,
+                address,
+                name: names.device_name(d),
+                outcome,
+                policy: decided_by,
+            }
+        })
+        .collect();
+
+    // Only LE pairings which were written need the local IRK they were made with,
+    // kept ones still work with the current one
+    let mut identities = vec![];
+    for adapter in keys.adapters.iter().filter(|a| {
+        win_devices.iter().zip(devices.iter()).any(|(d, report)| {
+            d.ltk.is_some()
+                && d.parent_address.0 == a.address.0
+                && matches!(report.outcome, report::Outcome::Updated)
+        })
+    }) {
+        let adapter_name = linux_bt_device::BtAddress::from(adapter.address.clone()).0;
+        match identity::update(root, adapter, keys.system, &backup_dir, interaction) {
+            Ok(Some(change)) => {
+                // the adapter stands for its own device in the ledger
+                ledger_entries.push(ledger::LedgerEntry {
+                    time: format_time(now),
+                    direction,
+                    source: source.to_string(),
+                    adapter: adapter_name.clone(),
+                    device: adapter_name.clone(),
+                    name: None,
+                    sections: vec![change],
+                });
+                identities.push(adapter_name);
+            }
+            Ok(None) => (),
+            Err(e) => error!(
+                "can't update the identity of adapter {} in {:?}: {:?}",
+                adapter_name, root, e
+            ),
+        }
+    }
+
+    if let Err(e) = ledger::append(root, &ledger_entries) {
+        warn!("can't record changes in the ledger of {:?}: {:?}", root, e);
+    }
+
+    report::RootReport {
+        root: root.to_path_buf(),
+        backup_dir: Some(backup_dir).filter(|_| {
+            !identities.is_empty()
+                || devices
+                    .iter()
+                    .any(|d| matches!(d.outcome, report::Outcome::Updated))
+        }),
+        identities,
+        devices,
+    }
+}
+
+/// Report of a linux installation the user chose not to update
+fn skipped_root(win_devices: &[UniBtDevice], root: &Path, names: &NameIndex) -> report::RootReport {
+    report::RootReport {
+        root: root.to_path_buf(),
+        backup_dir: None,
+        identities: vec![],
+        devices: win_devices
+            .iter()
+            .map(|d| report::DeviceReport {
+                adapter: linux_bt_device::BtAddress::from(d.parent_address.clone()).0,
+                address: linux_bt_device::BtAddress::from(d.address.clone()).0,
+                name: names.device_name(d),
+                outcome: report::Outcome::Skipped {
+                    reason: "not confirmed".to_string(),
+                },
+                policy: None,
+            })
+            .collect(),
+    }
+}
+
+/// What `update_linux_device` did with an info file
+enum DeviceUpdate {
+    /// Backed up and rewritten, with the changed key sections
+    Written(Vec<ledger::SectionChange>),
+    /// Keys from the source are already there
+    Unchanged,
+    /// Keys differ but `may_overwrite` said no
+    Kept,
+    /// Keys differ and the skip policy leaves the whole file alone, metadata included
+    Skipped,
+}
+
+/// Rewrites the info file in `d_path` after backing it up, `may_overwrite` is asked
+/// only when the keys differ. With `skip` the file is only compared
+fn update_linux_device(
+    uni_dev: &UniBtDevice,
+    d_path: &Path,
+    d_backup_dir: &Path,
+    policy: &Policy,
+    skip: bool,
+    may_overwrite: impl FnOnce(&UniBtDevice) -> CustomResult<bool>,
+) -> CustomResult<DeviceUpdate> {
+    let info_path = d_path.join("info");
+    let info_str = read_to_string(&info_path).map_err(|e| e.into())?;
+
+    let linux_dev: linux_bt_device::BtDevice =
+        serde_ini::from_str(&info_str).map_err(|e| e.into())?;
+    let current = serde_ini::to_string(&linux_dev).map_err(|e| e.into())?;
+    let current_sections = linux_dev.key_sections();
+
+    let updated_linux_dev = build_linux_device(linux_dev.clone(), uni_dev, policy.fill_metadata);
+    let updated = serde_ini::to_string(&updated_linux_dev).map_err(|e| e.into())?;
+    let updated_sections = updated_linux_dev.key_sections();
+
+    if skip && current_sections != updated_sections {
+        return Ok(DeviceUpdate::Skipped);
+    }
+    if skip || updated == current {
+        debug!("{:?} is already up to date", d_path);
+        return Ok(DeviceUpdate::Unchanged);
+    }
+
+    if current_sections != updated_sections && !may_overwrite(uni_dev)? {
+        return Ok(DeviceUpdate::Kept);
+    }
+    // only keys which are written have to match the address type
+    if uni_dev.ltk.is_some() {
+        check_address_type(&linux_dev, uni_dev)?;
+    }
+
+    create_dir_all(d_backup_dir).map_err(|e| e.into())?;
+    copy(&info_path, d_backup_dir.join("info")).map_err(|e| e.into())?;
+
+    let mut file = File::create(&info_path).map_err(|e| e.into())?;
+    file.write_all(updated.as_bytes()).map_err(|e| e.into())?;
+    Ok(DeviceUpdate::Written(ledger::changed_sections(
+        &current_sections,
+        &updated_sections,
+    )))
+}
+
+/// Bluez loads LE keys only for the address type the device was paired with, keys
+/// from a pairing with another type would be ignored
+fn check_address_type(
+    linux_dev: &linux_bt_device::BtDevice,
+    uni_dev: &UniBtDevice,
+) -> CustomResult<()> {
+    let expected = linux_bt_device::bluez_address_type(uni_dev.address.1);
+    let current = linux_dev
+        .general
+        .as_ref()
+        .and_then(|g| g.address_type.as_deref());
+
+    match (expected, current) {
+        (Some(expected), Some(current)) if expected != current => Err(format!(
+            "address type is {} in linux but {} in the source, bluez would ignore the keys. \
+            Pair the device again in linux",
+            current, expected
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Roots of linux installations which have bluetooth pairings, the running system
+/// is always `/`, others are mounted partitions, e.g. `["/", "/mnt/fedora"]`
+pub fn get_linux_roots() -> CustomResult<Vec<PathBuf>> {
+    let mounts = read_to_string("/proc/mounts").map_err(|e| e.into())?;
+
+    let mut roots = vec![];
+    if Path::new("/").join(LINUX_BT_DIR).is_dir() {
+        roots.push(PathBuf::from("/"));
+    }
+
+    let other_roots = mounts
+        .split('\n')
+        .filter(|l| l.starts_with("/dev/"))
+        .filter_map(|l| l.split(' ').nth(1))
+        .filter(|mnt_p| *mnt_p != "/")
+        .map(PathBuf::from)
+        .filter(|mnt_p| mnt_p.join(LINUX_BT_DIR).is_dir() && mnt_p.join(LINUX_OS_RELEASE).exists());
+    for root in other_roots {
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+
+    debug!("found {} linux root(s)", roots.len());
+    Ok(roots)
+}
+
+/// `roots` given with `--root` or, without them, the ones picked by the user among
+/// detected ones
+pub fn choose_linux_roots(
+    roots: &[PathBuf],
+    interaction: &Interaction,
+) -> CustomResult<Vec<PathBuf>> {
+    if !roots.is_empty() {
+        return Ok(roots.to_vec());
+    }
+
+    let roots = get_linux_roots()?;
+    if roots.len() <= 1 {
+        return Ok(roots);
+    }
+
+    if !interaction.interactive {
+        info!("prompts are disabled, updating only the running system");
+        return Ok(roots.into_iter().take(1).collect());
+    }
+
+    let roots_str: Vec<_> = roots.iter().map(|r| r.display().to_string()).collect();
+    let chosen = MultiSelect::new(
+        "multiple linux installations detected. which ones to update?",
+        roots_str,
+    )
+    .with_default(&[0])
+    .prompt()
+    .map_err(|e| e.into())?;
+
+    Ok(chosen.into_iter().map(PathBuf::from).collect())
+}
+
+/// Applies keys of the source device on top of the existing linux device,
+/// `fill_metadata` also copies its name, class and device id
+pub fn build_linux_device(
+    linux_dev: linux_bt_device::BtDevice,
+    uni_dev: &UniBtDevice,
+    fill_metadata: bool,
+) -> linux_bt_device::BtDevice {
+    let mut builder = linux_bt_device::BtDeviceBuilder::new().device(linux_dev);
+
+    if let Some(link_key) = uni_dev.link_key.clone() {
+        builder = builder.link_key(link_key);
+    }
+
+    if let Some(ltk) = uni_dev.ltk.clone() {
+        builder = builder.ltk(ltk);
+    }
+
+    if let Some(irk) = uni_dev.irk.clone() {
+        builder = builder.irk(irk);
+    }
+
+    if let Some(csrk) = uni_dev.csrk.clone() {
+        builder = builder.csrk(csrk);
+    }
+
+    if let Some(e_div) = uni_dev.e_div.clone() {
+        builder = builder.e_div(e_div);
+    }
+
+    if let Some(e_rand) = uni_dev.e_rand.clone() {
+        builder = builder.e_rand(e_rand);
+    }
+
+    if let Some(le_security) = uni_dev.le_security.clone() {
+        builder = builder.le_security(le_security);
+    }
+
+    if uni_dev.ltk.is_some() {
+        builder = builder.address_type(uni_dev.address.1);
+    }
+
+    if fill_metadata {
+        if let Some(meta) = uni_dev.meta.clone() {
+            builder = builder.meta(meta);
+        }
+    }
+
+    builder.build()
+}
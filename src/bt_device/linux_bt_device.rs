@@ -2,24 +2,39 @@ use serde::{Deserialize, Serialize};
 
 use super::uni_bt_device;
 
+/// Bits of `Authenticated`, bluez stores the mgmt LTK type: 0 unauthenticated,
+/// 1 authenticated, 2 and 3 the same for Secure Connections (P-256)
+const LTK_AUTHENTICATED: u8 = 0x01;
+const LTK_P256: u8 = 0x02;
+
+pub use linux_bt_helpers::{authenticated, bytes_to_linux_hex_key, linux_hex_key_to_bytes};
+
 pub struct BtDeviceBuilder {
     device: Option<BtDevice>,
+    link_key: Option<uni_bt_device::LinkKey>,
     ltk: Option<uni_bt_device::Ltk>,
     e_rand: Option<uni_bt_device::ERand>,
     e_div: Option<uni_bt_device::EDiv>,
     irk: Option<uni_bt_device::Irk>,
     csrk: Option<uni_bt_device::Csrk>,
+    le_security: Option<uni_bt_device::LeSecurity>,
+    address_type: Option<uni_bt_device::AddressType>,
+    meta: Option<uni_bt_device::DeviceMeta>,
 }
 
 impl BtDeviceBuilder {
     pub fn new() -> Self {
         Self {
             device: None,
+            link_key: None,
             ltk: None,
             e_rand: None,
             e_div: None,
             irk: None,
             csrk: None,
+            le_security: None,
+            address_type: None,
+            meta: None,
         }
     }
 
@@ -28,6 +43,11 @@ impl BtDeviceBuilder {
         self
     }
 
+    pub fn link_key(mut self, link_key: uni_bt_device::LinkKey) -> Self {
+        self.link_key = Some(link_key);
+        self
+    }
+
     pub fn ltk(mut self, ltk: uni_bt_device::Ltk) -> Self {
         self.ltk = Some(ltk);
         self
@@ -53,6 +73,25 @@ impl BtDeviceBuilder {
         self
     }
 
+    /// Decides `Authenticated`, `EncSize` and which LTK section is written, without it
+    /// only existing LTK sections get the new key
+    pub fn le_security(mut self, le_security: uni_bt_device::LeSecurity) -> Self {
+        self.le_security = Some(le_security);
+        self
+    }
+
+    /// Kind of the LE address, written to `General.AddressType` unless it's private
+    pub fn address_type(mut self, address_type: uni_bt_device::AddressType) -> Self {
+        self.address_type = Some(address_type);
+        self
+    }
+
+    /// Name, class and device id to fill in, values missing in `meta` are kept
+    pub fn meta(mut self, meta: uni_bt_device::DeviceMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
     pub fn build(mut self) -> BtDevice {
         let mut device = if let Some(dev) = self.device.take() {
             dev
@@ -60,19 +99,15 @@ impl BtDeviceBuilder {
             panic!("didn't provide existing device to build upon");
         };
 
-        let ltk = if let Some(lt) = self.ltk.as_ref() {
-            lt
-        } else {
-            panic!("didn't provide a new ltk to replace with");
-        };
-
         if let Some(link_key) = device.link_key.as_mut() {
-            let new_link_key = LinkKey {
-                key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
-                r#type: link_key.r#type.clone(),
-                pin_length: link_key.pin_length.clone(),
-            };
-            let _ = std::mem::replace(link_key, new_link_key);
+            if let Some(new_key) = self.link_key.as_ref() {
+                let new_link_key = LinkKey {
+                    key: linux_bt_helpers::bytes_to_linux_hex_key(&new_key.0),
+                    r#type: link_key.r#type.clone(),
+                    pin_length: link_key.pin_length.clone(),
+                };
+                let _ = std::mem::replace(link_key, new_link_key);
+            }
         }
 
         if let Some(identity_resolving_key) = device.identity_resolving_key.as_mut() {
@@ -84,28 +119,6 @@ impl BtDeviceBuilder {
             }
         }
 
-        if let Some(peripheral_long_term_key) = device.peripheral_long_term_key.as_mut() {
-            let new_peripheral_long_term_key = PeripheralLongTermKey {
-                key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
-                authenticated: peripheral_long_term_key.authenticated.clone(),
-                enc_size: peripheral_long_term_key.enc_size.clone(),
-                e_div: peripheral_long_term_key.e_div.clone(),
-                rand: peripheral_long_term_key.rand.clone(),
-            };
-            let _ = std::mem::replace(peripheral_long_term_key, new_peripheral_long_term_key);
-        }
-
-        if let Some(slave_long_term_key) = device.slave_long_term_key.as_mut() {
-            let new_slave_long_term_key = SlaveLongTermKey {
-                key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
-                authenticated: slave_long_term_key.authenticated.clone(),
-                enc_size: slave_long_term_key.enc_size.clone(),
-                e_div: slave_long_term_key.e_div.clone(),
-                rand: slave_long_term_key.rand.clone(),
-            };
-            let _ = std::mem::replace(slave_long_term_key, new_slave_long_term_key);
-        }
-
         if let Some(local_signature_key) = device.local_signature_key.as_mut() {
             if let Some(csrk) = self.csrk {
                 let new_local_signature_key = LocalSignatureKey {
@@ -115,18 +128,123 @@ impl BtDeviceBuilder {
             }
         }
 
-        if let Some(long_term_key) = device.long_term_key.as_mut() {
-            if let Some(e_rand) = self.e_rand {
-                if let Some(e_div) = self.e_div {
-                    let new_long_term_key = LongTermKey {
-                        key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
-                        authenticated: long_term_key.authenticated.clone(),
-                        enc_size: long_term_key.enc_size.clone(),
-                        e_div: linux_bt_helpers::bytes_to_linux_hex_key(&e_div.0),
-                        rand: linux_bt_helpers::bytes_to_linux_hex_key(&e_rand.0),
-                    };
-                    let _ = std::mem::replace(long_term_key, new_long_term_key);
+        if let Some(ltk) = self.ltk.as_ref() {
+            match self.le_security.take() {
+                Some(security) => {
+                    let key = linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0);
+                    let authenticated = linux_bt_helpers::authenticated(&security).to_string();
+                    let enc_size = security.key_size.to_string();
+
+                    if security.secure_connections {
+                        // Both sides derive the key, bluez keeps it as the peripheral one,
+                        // named SlaveLongTermKey before bluez 5.62
+                        device.long_term_key = None;
+                        let has_slave = device.slave_long_term_key.is_some();
+                        if has_slave {
+                            device.slave_long_term_key = Some(SlaveLongTermKey {
+                                key: key.clone(),
+                                authenticated: authenticated.clone(),
+                                enc_size: enc_size.clone(),
+                                e_div: "0".to_string(),
+                                rand: "0".to_string(),
+                            });
+                        }
+                        if !has_slave || device.peripheral_long_term_key.is_some() {
+                            device.peripheral_long_term_key = Some(PeripheralLongTermKey {
+                                key,
+                                authenticated,
+                                enc_size,
+                                e_div: "0".to_string(),
+                                rand: "0".to_string(),
+                            });
+                        }
+                    } else {
+                        device.peripheral_long_term_key = None;
+                        device.slave_long_term_key = None;
+                        device.long_term_key = Some(LongTermKey {
+                            key,
+                            authenticated,
+                            enc_size,
+                            e_div: self
+                                .e_div
+                                .map_or(0, |e_div| u32::from_le_bytes(e_div.0))
+                                .to_string(),
+                            rand: self
+                                .e_rand
+                                .map_or(0, |e_rand| u64::from_le_bytes(e_rand.0))
+                                .to_string(),
+                        });
+                    }
                 }
+                None => {
+                    if let Some(peripheral_long_term_key) = device.peripheral_long_term_key.as_mut()
+                    {
+                        let new_peripheral_long_term_key = PeripheralLongTermKey {
+                            key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
+                            authenticated: peripheral_long_term_key.authenticated.clone(),
+                            enc_size: peripheral_long_term_key.enc_size.clone(),
+                            e_div: peripheral_long_term_key.e_div.clone(),
+                            rand: peripheral_long_term_key.rand.clone(),
+                        };
+                        let _ = std::mem::replace(
+                            peripheral_long_term_key,
+                            new_peripheral_long_term_key,
+                        );
+                    }
+
+                    if let Some(slave_long_term_key) = device.slave_long_term_key.as_mut() {
+                        let new_slave_long_term_key = SlaveLongTermKey {
+                            key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
+                            authenticated: slave_long_term_key.authenticated.clone(),
+                            enc_size: slave_long_term_key.enc_size.clone(),
+                            e_div: slave_long_term_key.e_div.clone(),
+                            rand: slave_long_term_key.rand.clone(),
+                        };
+                        let _ = std::mem::replace(slave_long_term_key, new_slave_long_term_key);
+                    }
+
+                    if let Some(long_term_key) = device.long_term_key.as_mut() {
+                        if let Some(e_rand) = self.e_rand {
+                            if let Some(e_div) = self.e_div {
+                                let new_long_term_key = LongTermKey {
+                                    key: linux_bt_helpers::bytes_to_linux_hex_key(&ltk.0),
+                                    authenticated: long_term_key.authenticated.clone(),
+                                    enc_size: long_term_key.enc_size.clone(),
+                                    e_div: u32::from_le_bytes(e_div.0).to_string(),
+                                    rand: u64::from_le_bytes(e_rand.0).to_string(),
+                                };
+                                let _ = std::mem::replace(long_term_key, new_long_term_key);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(address_type) = self.address_type.and_then(bluez_address_type) {
+            if let Some(general) = device.general.as_mut() {
+                general.address_type = Some(address_type.to_string());
+            }
+        }
+
+        if let Some(meta) = self.meta.take() {
+            if let Some(general) = device.general.as_mut() {
+                if let Some(name) = meta.name {
+                    general.name = name;
+                }
+
+                if let Some(class) = meta.class {
+                    general.class = Some(format!("0x{:06x}", class));
+                }
+            }
+
+            if let Some(device_id) = meta.device_id {
+                device.device_id = Some(DeviceId {
+                    source: device_id.source.to_string(),
+                    vendor: device_id.vendor.to_string(),
+                    product: device_id.product.to_string(),
+                    version: device_id.version.to_string(),
+                });
             }
         }
 
@@ -134,6 +252,16 @@ impl BtDeviceBuilder {
     }
 }
 
+/// Value of `General.AddressType` bluez uses for an LE address, `None` for private ones
+/// which bluez doesn't keep keys under
+pub fn bluez_address_type(address_type: uni_bt_device::AddressType) -> Option<&'static str> {
+    match address_type {
+        uni_bt_device::AddressType::Public => Some("public"),
+        uni_bt_device::AddressType::StaticRandom => Some("static"),
+        uni_bt_device::AddressType::Other => None,
+    }
+}
+
 pub struct BtAddress(pub String);
 
 impl From<uni_bt_device::Address> for BtAddress {
@@ -143,7 +271,18 @@ impl From<uni_bt_device::Address> for BtAddress {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl BtAddress {
+    /// "4C:87:5D:26:DC:9F" -> [0x4c, 0x87, ...], `None` if it isn't an address
+    pub fn to_uni(
+        &self,
+        address_type: uni_bt_device::AddressType,
+    ) -> Option<uni_bt_device::Address> {
+        let bytes = linux_bt_helpers::linux_hex_address_to_bytes(&self.0)?;
+        Some(uni_bt_device::Address(bytes, address_type))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BtDevice {
     #[serde(rename = "General")]
     pub general: Option<General>,
@@ -165,14 +304,153 @@ pub struct BtDevice {
     pub long_term_key: Option<LongTermKey>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl BtDevice {
+    /// Key material of every key section present in the device
+    ///
+    /// ## Example
+    /// ```
+    /// ("LinkKey", "786DC4332D385A48C4E718FE0B84FF20")
+    /// ("LongTermKey", "128515400334819AA35B2D6C010BCEB1:2:16:0:0")
+    /// ```
+    pub fn key_sections(&self) -> Vec<(&'static str, String)> {
+        let mut sections = vec![];
+
+        if let Some(link_key) = self.link_key.as_ref() {
+            sections.push(("LinkKey", link_key.key.clone()));
+        }
+
+        if let Some(irk) = self.identity_resolving_key.as_ref() {
+            sections.push(("IdentityResolvingKey", irk.key.clone()));
+        }
+
+        if let Some(ltk) = self.slave_long_term_key.as_ref() {
+            sections.push((
+                "SlaveLongTermKey",
+                format!(
+                    "{}:{}:{}:{}:{}",
+                    ltk.key, ltk.authenticated, ltk.enc_size, ltk.e_div, ltk.rand
+                ),
+            ));
+        }
+
+        if let Some(ltk) = self.peripheral_long_term_key.as_ref() {
+            sections.push((
+                "PeripheralLongTermKey",
+                format!(
+                    "{}:{}:{}:{}:{}",
+                    ltk.key, ltk.authenticated, ltk.enc_size, ltk.e_div, ltk.rand
+                ),
+            ));
+        }
+
+        if let Some(csrk) = self.local_signature_key.as_ref() {
+            sections.push(("LocalSignatureKey", csrk.key.clone()));
+        }
+
+        if let Some(ltk) = self.long_term_key.as_ref() {
+            sections.push((
+                "LongTermKey",
+                format!(
+                    "{}:{}:{}:{}:{}",
+                    ltk.key, ltk.authenticated, ltk.enc_size, ltk.e_div, ltk.rand
+                ),
+            ));
+        }
+
+        sections
+    }
+
+    /// Type of the address the device was paired with, devices without
+    /// `General.AddressType` are classic ones which always use their public address
+    pub fn address_type(&self) -> uni_bt_device::AddressType {
+        match self
+            .general
+            .as_ref()
+            .and_then(|g| g.address_type.as_deref())
+        {
+            None | Some("public") => uni_bt_device::AddressType::Public,
+            Some("static") => uni_bt_device::AddressType::StaticRandom,
+            Some(_) => uni_bt_device::AddressType::Other,
+        }
+    }
+
+    /// Keys of the device for writing them elsewhere, the LTK comes from `LongTermKey`
+    /// or, for Secure Connections, from `PeripheralLongTermKey`/`SlaveLongTermKey`
+    pub fn uni_device(
+        &self,
+        parent_address: uni_bt_device::Address,
+        address: uni_bt_device::Address,
+    ) -> uni_bt_device::UniBtDevice {
+        let key = linux_bt_helpers::linux_hex_key_to_bytes;
+
+        let ltk = self
+            .long_term_key
+            .as_ref()
+            .map(|k| (&k.key, &k.authenticated, &k.enc_size, &k.e_div, &k.rand))
+            .or_else(|| {
+                self.peripheral_long_term_key
+                    .as_ref()
+                    .map(|k| (&k.key, &k.authenticated, &k.enc_size, &k.e_div, &k.rand))
+            })
+            .or_else(|| {
+                self.slave_long_term_key
+                    .as_ref()
+                    .map(|k| (&k.key, &k.authenticated, &k.enc_size, &k.e_div, &k.rand))
+            });
+        let le_security = ltk.and_then(|(_, authenticated, enc_size, _, _)| {
+            let authenticated = authenticated.parse::<u8>().ok()?;
+            Some(uni_bt_device::LeSecurity {
+                mitm: authenticated & LTK_AUTHENTICATED != 0,
+                secure_connections: authenticated & LTK_P256 != 0,
+                key_size: enc_size.parse().ok()?,
+            })
+        });
+        let e_div = ltk.and_then(|(_, _, _, e_div, _)| e_div.parse::<u32>().ok());
+        let e_rand = ltk.and_then(|(_, _, _, _, rand)| rand.parse::<u64>().ok());
+        let ltk = ltk.and_then(|(k, _, _, _, _)| key(k));
+
+        uni_bt_device::UniBtDevice {
+            address,
+            parent_address,
+            link_key: self
+                .link_key
+                .as_ref()
+                .and_then(|l| key(&l.key))
+                .map(uni_bt_device::LinkKey),
+            ltk: ltk.map(uni_bt_device::Ltk),
+            e_rand: e_rand.map(|r| uni_bt_device::ERand(r.to_le_bytes())),
+            e_div: e_div.map(|d| uni_bt_device::EDiv(d.to_le_bytes())),
+            irk: self
+                .identity_resolving_key
+                .as_ref()
+                .and_then(|k| key(&k.key))
+                .map(uni_bt_device::Irk),
+            csrk: self
+                .local_signature_key
+                .as_ref()
+                .and_then(|k| key(&k.key))
+                .map(uni_bt_device::Csrk),
+            le_security,
+            meta: self.general.as_ref().map(|g| uni_bt_device::DeviceMeta {
+                name: Some(g.name.clone()).filter(|n| !n.is_empty()),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct General {
     #[serde(rename = "Name")]
     pub name: String,
+    /// Class=0x240404
+    #[serde(rename = "Class")]
+    pub class: Option<String>,
     #[serde(rename = "Appearance")]
     pub appearance: Option<String>,
+    /// Only LE devices have it
     #[serde(rename = "AddressType")]
-    pub address_type: String,
+    pub address_type: Option<String>,
     #[serde(rename = "SupportedTechnologies")]
     pub supported_technologies: String,
     #[serde(rename = "Trusted")]
@@ -185,7 +463,7 @@ pub struct General {
     pub services: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DeviceId {
     #[serde(rename = "Source")]
     source: String,
@@ -197,7 +475,7 @@ pub struct DeviceId {
     version: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConnectionParameters {
     #[serde(rename = "MinInterval")]
     min_interval: String,
@@ -209,7 +487,7 @@ pub struct ConnectionParameters {
     timeout: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LinkKey {
     /// Key=786DC4332D385A48C4E718FE0B84FF20
     #[serde(rename = "Key")]
@@ -222,14 +500,14 @@ pub struct LinkKey {
     pin_length: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IdentityResolvingKey {
     /// Key=786DC4332D385A48C4E718FE0B84FF20
     #[serde(rename = "Key")]
     key: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SlaveLongTermKey {
     /// Key=128515400334819AA35B2D6C010BCEB1
     #[serde(rename = "Key")]
@@ -248,7 +526,7 @@ pub struct SlaveLongTermKey {
     rand: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PeripheralLongTermKey {
     /// Key=128515400334819AA35B2D6C010BCEB1
     #[serde(rename = "Key")]
@@ -267,14 +545,14 @@ pub struct PeripheralLongTermKey {
     rand: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LocalSignatureKey {
     /// Key=128515400334819AA35B2D6C010BCEB1
     #[serde(rename = "Key")]
     key: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LongTermKey {
     /// Key=128515400334819AA35B2D6C010BCEB1
     #[serde(rename = "Key")]
@@ -285,15 +563,73 @@ pub struct LongTermKey {
     /// EncSize=16
     #[serde(rename = "EncSize")]
     enc_size: String,
-    /// EDiv=0
+    /// EDiv=41969, decimal
     #[serde(rename = "EDiv")]
     e_div: String,
-    /// Rand=0
+    /// Rand=10265420091337917996, decimal
     #[serde(rename = "Rand")]
     rand: String,
 }
 
+/// Local identity of an adapter, `<adapter>/identity`
+///
+/// ## Example
+/// ```
+/// [General]
+/// IdentityResolvingKey=9E0C27A4F5E0B5C8D2C1A9D84E3F1B67
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdapterIdentity {
+    #[serde(rename = "General")]
+    general: IdentityGeneral,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IdentityGeneral {
+    #[serde(rename = "IdentityResolvingKey")]
+    identity_resolving_key: String,
+}
+
+impl AdapterIdentity {
+    pub fn new(irk: &uni_bt_device::Irk) -> Self {
+        Self {
+            general: IdentityGeneral {
+                identity_resolving_key: linux_bt_helpers::bytes_to_linux_hex_key(&irk.0),
+            },
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.general.identity_resolving_key
+    }
+}
+
 mod linux_bt_helpers {
+    use super::{uni_bt_device, LTK_AUTHENTICATED, LTK_P256};
+
+    /// `Authenticated` of an LTK section is the mgmt key type, e.g. 3 for a
+    /// Secure Connections pairing with MITM protection
+    pub fn authenticated(security: &uni_bt_device::LeSecurity) -> u8 {
+        let mut key_type = 0;
+        if security.mitm {
+            key_type |= LTK_AUTHENTICATED;
+        }
+        if security.secure_connections {
+            key_type |= LTK_P256;
+        }
+        key_type
+    }
+
+    /// "786DC4332D385A48C4E718FE0B84FF20" -> [0x78, 0x6d, 0xc4, ...]
+    pub fn linux_hex_key_to_bytes(hex: &str) -> Option<[u8; 16]> {
+        let bytes: Vec<_> = hex
+            .as_bytes()
+            .chunks(2)
+            .map(|b| u8::from_str_radix(std::str::from_utf8(b).ok()?, 16).ok())
+            .collect::<Option<_>>()?;
+        bytes.try_into().ok()
+    }
+
     pub fn bytes_to_linux_hex_key(bytes: &[u8]) -> String {
         bytes
             .iter()
@@ -301,6 +637,15 @@ mod linux_bt_helpers {
             .collect()
     }
 
+    /// "4C:87:5D:26:DC:9F" -> [0x4c, 0x87, 0x5d, 0x26, 0xdc, 0x9f]
+    pub fn linux_hex_address_to_bytes(hex: &str) -> Option<[u8; 6]> {
+        let bytes: Vec<_> = hex
+            .split(':')
+            .map(|b| u8::from_str_radix(b, 16).ok())
+            .collect::<Option<_>>()?;
+        bytes.try_into().ok()
+    }
+
     pub fn bytes_to_linux_hex_address(bytes: &[u8]) -> String {
         bytes
             .iter()
@@ -309,3 +654,41 @@ mod linux_bt_helpers {
             .join(":")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::linux_bt_helpers::*;
+    use super::uni_bt_device::LeSecurity;
+
+    #[test]
+    fn converts_keys_and_addresses() {
+        let key = "786DC4332D385A48C4E718FE0B84FF20";
+        let bytes = linux_hex_key_to_bytes(key).unwrap();
+        assert_eq!(bytes[..3], [0x78, 0x6d, 0xc4]);
+        assert_eq!(bytes_to_linux_hex_key(&bytes), key);
+        assert_eq!(linux_hex_key_to_bytes("786DC433"), None, "too short");
+        assert_eq!(
+            linux_hex_key_to_bytes("ZZ6DC4332D385A48C4E718FE0B84FF20"),
+            None
+        );
+
+        let address = "4C:87:5D:26:DC:9F";
+        let bytes = linux_hex_address_to_bytes(address).unwrap();
+        assert_eq!(bytes, [0x4c, 0x87, 0x5d, 0x26, 0xdc, 0x9f]);
+        assert_eq!(bytes_to_linux_hex_address(&bytes), address);
+        assert_eq!(linux_hex_address_to_bytes("4C:87:5D"), None);
+    }
+
+    #[test]
+    fn maps_security_to_key_types() {
+        let security = |mitm, secure_connections| LeSecurity {
+            mitm,
+            secure_connections,
+            key_size: 16,
+        };
+        assert_eq!(authenticated(&security(false, false)), 0);
+        assert_eq!(authenticated(&security(true, false)), 1);
+        assert_eq!(authenticated(&security(false, true)), 2);
+        assert_eq!(authenticated(&security(true, true)), 3);
+    }
+}
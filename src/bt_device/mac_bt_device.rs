@@ -0,0 +1,258 @@
+use log::{debug, warn};
+use plist::{Dictionary, Value};
+
+use super::uni_bt_device;
+
+/// Top level keys of com.apple.Bluetooth.plist that hold pairing keys
+pub fn pairing_dictionaries(plist: &Value) -> (Option<&Dictionary>, Option<&Dictionary>) {
+    let root = plist.as_dictionary();
+    let link_keys = root
+        .and_then(|r| r.get("LinkKeys"))
+        .and_then(|v| v.as_dictionary());
+    let smp_keys = root
+        .and_then(|r| r.get("SMPDistributionKeys"))
+        .and_then(|v| v.as_dictionary());
+    (link_keys, smp_keys)
+}
+
+/// Accepts `LinkKeys` dictionary of classic devices grouped by adapter
+///
+/// ## Example
+/// ```
+/// "c0-fb-f9-60-1c-13": {
+///     "4c-87-5d-26-dc-9f": <20ff840b fe18e7c4 485a382d 33c46d78>
+/// }
+/// ```
+pub fn link_key_devices(link_keys: &Dictionary) -> Vec<uni_bt_device::UniBtDevice> {
+    link_keys
+        .iter()
+        .filter_map(|(adapter, devices)| {
+            let parent_address = mac_plist_helpers::address(adapter)?;
+            Some((parent_address, devices.as_dictionary()?))
+        })
+        .flat_map(|(parent_address, devices)| {
+            devices
+                .iter()
+                .filter_map(|(address, link_key)| {
+                    let device = uni_bt_device::UniBtDevice {
+                        address: mac_plist_helpers::address(address)?,
+                        parent_address: parent_address.clone(),
+                        link_key: Some(uni_bt_device::LinkKey(mac_plist_helpers::reversed(
+                            link_key,
+                        )?)),
+                        ltk: None,
+                        e_rand: None,
+                        e_div: None,
+                        irk: None,
+                        csrk: None,
+                        le_security: None,
+                        meta: None,
+                    };
+                    debug!("mac link key device {}", device.address);
+                    Some(device)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Accepts `SMPDistributionKeys` dictionary of LE devices grouped by adapter
+///
+/// ## Example
+/// ```
+/// "c0-fb-f9-60-1c-13": {
+///     "c8-29-0a-11-f4-c1": {
+///         "LTK": <d56bad67 e94fc618 d0c7be1e 3b1990c2>,
+///         "IRK": <8a33b06e 2a966196 d0eeeee3 3ef8eafc>,
+///         "EDIV": <0000>,
+///         "RAND": <00000000 00000000>
+///     }
+/// }
+/// ```
+pub fn le_devices(smp_keys: &Dictionary) -> Vec<uni_bt_device::UniBtDevice> {
+    smp_keys
+        .iter()
+        .filter_map(|(adapter, devices)| {
+            let parent_address = mac_plist_helpers::address(adapter)?;
+            Some((parent_address, devices.as_dictionary()?))
+        })
+        .flat_map(|(parent_address, devices)| {
+            devices
+                .iter()
+                .filter_map(|(address, keys)| {
+                    let keys = keys.as_dictionary()?;
+                    // The plist doesn't say which kind of LE address it is
+                    let address = uni_bt_device::Address(
+                        mac_plist_helpers::address(address)?.0,
+                        uni_bt_device::AddressType::Other,
+                    );
+
+                    let ltk = match keys.get("LTK").and_then(mac_plist_helpers::reversed) {
+                        Some(ltk) => ltk,
+                        None => {
+                            warn!("mac device {} has no usable LTK", address);
+                            return None;
+                        }
+                    };
+
+                    let device = uni_bt_device::UniBtDevice {
+                        address,
+                        parent_address: parent_address.clone(),
+                        link_key: None,
+                        ltk: Some(uni_bt_device::Ltk(ltk)),
+                        e_rand: keys
+                            .get("RAND")
+                            .and_then(mac_plist_helpers::reversed)
+                            .map(uni_bt_device::ERand),
+                        e_div: keys
+                            .get("EDIV")
+                            .and_then(mac_plist_helpers::e_div)
+                            .map(uni_bt_device::EDiv),
+                        irk: keys
+                            .get("IRK")
+                            .and_then(mac_plist_helpers::reversed)
+                            .map(uni_bt_device::Irk),
+                        csrk: keys
+                            .get("CSRK")
+                            .and_then(mac_plist_helpers::reversed)
+                            .map(uni_bt_device::Csrk),
+                        le_security: None,
+                        meta: None,
+                    };
+                    debug!("mac le device {}", device.address);
+                    Some(device)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+mod mac_plist_helpers {
+    use plist::Value;
+
+    use crate::bt_device::uni_bt_device;
+
+    /// "4c-87-5d-26-dc-9f" -> [u8; 6]
+    pub fn address(address: &str) -> Option<uni_bt_device::Address> {
+        let bytes: Vec<_> = address
+            .split('-')
+            .map(|b| u8::from_str_radix(b, 16).ok())
+            .collect::<Option<_>>()?;
+        Some(uni_bt_device::Address(
+            bytes.try_into().ok()?,
+            uni_bt_device::AddressType::Public,
+        ))
+    }
+
+    /// Apple keeps keys in the reversed byte order
+    ///
+    /// <20ff840b fe18e7c4 485a382d 33c46d78> -> [0x78, 0x6d, 0xc4, 0x33, ...]
+    pub fn reversed<const N: usize>(value: &Value) -> Option<[u8; N]> {
+        let bytes: Vec<_> = value.as_data()?.iter().rev().copied().collect();
+        bytes.try_into().ok()
+    }
+
+    /// EDIV is either <1234> or an integer, both big-endian
+    ///
+    /// <1234> -> [0x34, 0x12, 0x00, 0x00]
+    pub fn e_div(value: &Value) -> Option<[u8; 4]> {
+        if let Some(num) = value.as_unsigned_integer() {
+            return Some((num as u32).to_le_bytes());
+        }
+
+        let bytes: [u8; 2] = reversed(value)?;
+        Some([bytes[0], bytes[1], 0, 0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Value {
+        Value::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/macos/com.apple.Bluetooth.plist"
+        ))
+        .expect("fixture plist")
+    }
+
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("hex digit"))
+            .collect()
+    }
+
+    #[test]
+    fn reads_classic_devices() {
+        let plist = fixture();
+        let (link_keys, _) = pairing_dictionaries(&plist);
+        let devices = link_key_devices(link_keys.expect("LinkKeys"));
+
+        // The adapter named "not-an-adapter" is skipped
+        assert_eq!(devices.len(), 1);
+        let device = &devices[0];
+        assert_eq!(
+            device.parent_address.0,
+            [0xc0, 0xfb, 0xf9, 0x60, 0x1c, 0x13]
+        );
+        assert_eq!(device.address.0, [0x4c, 0x87, 0x5d, 0x26, 0xdc, 0x9f]);
+        assert_eq!(device.address.1, uni_bt_device::AddressType::Public);
+        assert_eq!(
+            device.link_key.as_ref().expect("link key").0.to_vec(),
+            hex("786DC4332D385A48C4E718FE0B84FF20")
+        );
+    }
+
+    #[test]
+    fn reads_le_devices() {
+        let plist = fixture();
+        let (_, smp_keys) = pairing_dictionaries(&plist);
+        let devices = le_devices(smp_keys.expect("SMPDistributionKeys"));
+
+        // e4-17-d8-5c-02-6a has no LTK
+        assert_eq!(devices.len(), 2);
+        let device = |address: [u8; 6]| {
+            devices
+                .iter()
+                .find(|d| d.address.0 == address)
+                .expect("device")
+        };
+
+        let legacy = device([0xc8, 0x29, 0x0a, 0x11, 0xf4, 0xc1]);
+        assert_eq!(legacy.address.1, uni_bt_device::AddressType::Other);
+        assert_eq!(
+            legacy.ltk.as_ref().expect("ltk").0.to_vec(),
+            hex("C290193B1EBEC7D018C64FE967AD6BD5")
+        );
+        assert_eq!(
+            legacy.irk.as_ref().expect("irk").0.to_vec(),
+            hex("FCEAF83EE3EEEED09661962A6EB0338A")
+        );
+        assert_eq!(
+            legacy.e_rand.as_ref().expect("rand").0.to_vec(),
+            hex("2CC65A3E2F19768E")
+        );
+        // <a3f1>
+        assert_eq!(legacy.e_div.as_ref().expect("ediv").0, [0xf1, 0xa3, 0, 0]);
+
+        let integer_ediv = device([0xd0, 0x03, 0x4b, 0x2a, 0x91, 0x7e]);
+        // 41969
+        assert_eq!(
+            integer_ediv.e_div.as_ref().expect("ediv").0,
+            [0xf1, 0xa3, 0, 0]
+        );
+        assert!(integer_ediv.irk.is_none());
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(
+            mac_plist_helpers::address("c0-fb-f9-60-1c-13").map(|a| a.0),
+            Some([0xc0, 0xfb, 0xf9, 0x60, 0x1c, 0x13])
+        );
+        assert!(mac_plist_helpers::address("c0-fb-f9-60-1c").is_none());
+        assert!(mac_plist_helpers::address("c0-fb-f9-60-1c-zz").is_none());
+    }
+}
@@ -1,3 +1,4 @@
 pub(crate) mod uni_bt_device;
 pub(crate) mod win_bt_device;
-pub(crate) mod linux_bt_device;
\ No newline at end of file
+pub(crate) mod linux_bt_device;
+pub(crate) mod mac_bt_device;
\ No newline at end of file
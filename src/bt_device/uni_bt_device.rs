@@ -1,29 +1,139 @@
-#[derive(Debug)]
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
 pub struct UniBtDevice {
 	pub address: Address,
 	pub parent_address: Address,
-	pub ltk: Ltk,
+	/// Classic (BR/EDR) pairing
+	pub link_key: Option<LinkKey>,
+	/// LE pairing, the rest of the keys belong to it
+	pub ltk: Option<Ltk>,
 	pub e_rand: Option<ERand>,
 	pub e_div: Option<EDiv>,
 	pub irk: Option<Irk>,
 	pub csrk: Option<Csrk>,
+	/// How the LE pairing was made, `None` when the source doesn't say
+	pub le_security: Option<LeSecurity>,
+	pub meta: Option<DeviceMeta>,
 }
 
+/// Keys of a local adapter itself rather than of a paired device
 #[derive(Debug, Clone)]
-pub struct Address(pub [u8; 6]);
+pub struct UniBtAdapter {
+	pub address: Address,
+	/// Local IRK, devices paired with LE privacy resolve the host's address with it
+	pub irk: Option<Irk>,
+}
 
 #[derive(Debug, Clone)]
+pub struct Address(pub [u8; 6], pub AddressType);
+
+/// Kind of an LE address, BR/EDR addresses are always public
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressType {
+	Public,
+	/// Random address which stays the same until the device is reset
+	StaticRandom,
+	/// Resolvable or non-resolvable private address, or one of unknown type
+	Other,
+}
+
+impl AddressType {
+	/// Kind of a random address, static ones have both most significant bits set
+	pub fn random(address: &[u8; 6]) -> Self {
+		if address[0] & 0xc0 == 0xc0 {
+			AddressType::StaticRandom
+		} else {
+			AddressType::Other
+		}
+	}
+}
+
+impl std::fmt::Display for Address {
+	/// [u8; 6] -> "4C:87:5D:26:DC:9F"
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let hex: Vec<_> = self.0.iter().map(|b| format!("{:02X}", b)).collect();
+		write!(f, "{}", hex.join(":"))
+	}
+}
+
+/// Dual-mode devices come as two entries with the same address, one with the link
+/// key and one with LE keys. Joins them into a single device
+pub fn merge_dual_mode(devices: Vec<UniBtDevice>) -> Vec<UniBtDevice> {
+	let mut merged: Vec<UniBtDevice> = vec![];
+
+	for device in devices {
+		let existing = merged.iter_mut().find(|d| {
+			d.address.0 == device.address.0 && d.parent_address.0 == device.parent_address.0
+		});
+
+		match existing {
+			Some(existing) => {
+				existing.link_key = existing.link_key.take().or(device.link_key);
+				if device.ltk.is_some() {
+					existing.ltk = device.ltk;
+					existing.e_rand = device.e_rand;
+					existing.e_div = device.e_div;
+					existing.irk = device.irk;
+					existing.csrk = device.csrk;
+					existing.le_security = device.le_security;
+					existing.address.1 = device.address.1;
+				}
+				existing.meta = existing.meta.take().or(device.meta);
+			}
+			None => merged.push(device),
+		}
+	}
+
+	merged
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkKey(pub [u8; 16]);
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ltk(pub [u8; 16]);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ERand(pub [u8; 8]);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EDiv(pub [u8; 4]);
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Irk(pub [u8; 16]);
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Csrk(pub [u8; 16]);
+
+/// Properties of an LE pairing which decide how the LTK is stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeSecurity {
+	/// Pairing was protected against man-in-the-middle, e.g. with a passkey
+	pub mitm: bool,
+	/// LE Secure Connections, both sides derive the same LTK and EDiv/Rand are zero
+	pub secure_connections: bool,
+	/// Encryption key size in bytes, 7 to 16
+	pub key_size: u8,
+}
+
+/// Descriptive data of a device which isn't needed for the pairing itself
+#[derive(Debug, Clone, Default)]
+pub struct DeviceMeta {
+	pub name: Option<String>,
+	/// Class of device, e.g. `0x240404` for headphones
+	pub class: Option<u32>,
+	pub device_id: Option<DeviceId>,
+	pub last_connected: Option<SystemTime>,
+	pub last_seen: Option<SystemTime>,
+}
+
 #[derive(Debug, Clone)]
-pub struct Csrk(pub [u8; 16]);
\ No newline at end of file
+pub struct DeviceId {
+	/// 1 for Bluetooth SIG assigned vendor ids, 2 for USB-IF ones
+	pub source: u16,
+	pub vendor: u16,
+	pub product: u16,
+	pub version: u16,
+}
@@ -1,14 +1,23 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use log::debug;
 use serde::Deserialize;
 
 use super::uni_bt_device;
 
+const ADDRESS_TYPE_PUBLIC: u32 = 0;
+const ADDRESS_TYPE_RANDOM: u32 = 1;
+const AUTH_REQ_MITM: u32 = 0x04;
+const AUTH_REQ_SC: u32 = 0x08;
+const MAX_KEY_SIZE: u8 = 16;
+
 pub struct BtDeviceBuilder {
     address: Option<KeyAddress>,
     parent_address: Option<KeyAddress>,
-    ltk: Option<Ltk>,
+    link_key: Option<LinkKey>,
     entries51: Option<BtDevice51>,
 }
 
@@ -17,14 +26,14 @@ impl BtDeviceBuilder {
         Self {
             address: None,
             parent_address: None,
-            ltk: None,
+            link_key: None,
             entries51: None,
         }
     }
 
-    /// Accepts ltk in the format `"hex:c2,90,19,3b,1e,be,c7,d0,18,c6,4f,e9,67,ad,6b,d5"`
-    pub fn ltk(mut self, ltk: String) -> Self {
-        self.ltk = Some(Ltk(ltk));
+    /// Accepts link key in the format `"hex:78,6d,c4,33,2d,38,5a,48,c4,e7,18,fe,0b,84,ff,20"`
+    pub fn link_key(mut self, link_key: String) -> Self {
+        self.link_key = Some(LinkKey(link_key));
         self
     }
 
@@ -73,21 +82,19 @@ impl BtDeviceBuilder {
             address.into()
         } else {
             if let Some(entries51) = self.entries51.as_ref() {
-                entries51.address.clone().into()
+                entries51.address()
             } else {
                 panic!("address of bluetooth device is not provided");
             }
         };
 
-        let ltk: uni_bt_device::Ltk = if let Some(ltk) = self.ltk {
-            ltk.into()
-        } else {
-            if let Some(entries51) = self.entries51.as_ref() {
-                entries51.ltk.clone().into()
-            } else {
-                panic!("ltk of bluetooth device is not provided");
-            }
-        };
+        let link_key: Option<uni_bt_device::LinkKey> = self.link_key.map(|v| v.into());
+
+        let ltk: Option<uni_bt_device::Ltk> = self.entries51.as_ref().map(|e| e.ltk.clone().into());
+
+        if link_key.is_none() && ltk.is_none() {
+            panic!("neither link key nor ltk of bluetooth device is provided");
+        }
 
         let e_rand: Option<uni_bt_device::ERand> = if let Some(entries51) = self.entries51.as_ref() {
             Some(entries51.e_rand.clone().into())
@@ -113,18 +120,63 @@ impl BtDeviceBuilder {
             None
         };
 
+        let le_security = self.entries51.as_ref().map(|e| e.le_security());
+
         uni_bt_device::UniBtDevice {
             address,
             parent_address,
+            link_key,
             ltk,
             e_rand,
             e_div,
             irk,
             csrk,
+            le_security,
+            meta: None,
         }
     }
 }
 
+/// Accepts address in the format `"4c875d26dc9f"` and hashmap with values of
+/// `...\Parameters\Devices\<addr>`
+///
+/// ## Example
+/// ```
+/// "Name": "hex:57,48,2d,31,30,30,30,58,4d,34,00"
+/// "COD": "dword:00240404"
+/// "VID": "dword:0000054c"
+/// "PID": "dword:00000d58"
+/// "Version": "dword:00000100"
+/// "LastConnected": "hex(b):b0,3e,6a,91,e2,1f,da,01"
+/// "LastSeen": "hex(b):b0,3e,6a,91,e2,1f,da,01"
+/// ```
+///
+/// Fails with the reason when a value is malformed, the metadata is only descriptive so
+/// the device is skipped rather than the whole registry
+pub fn device_meta(
+    address: String,
+    entries: HashMap<String, String>,
+) -> Result<(uni_bt_device::Address, uni_bt_device::DeviceMeta), String> {
+    let s = serde_ini::to_string(&entries).map_err(|e| format!("{:?}", e))?;
+    let meta: BtDeviceMeta = serde_ini::from_str(&s).map_err(|e| format!("{:?}", e))?;
+    Ok((KeyAddress(address).into(), meta.try_into()?))
+}
+
+/// Accepts address in the format `"c0fbf9601c13"` and hashmap with values of
+/// `...\Parameters\Keys\<adapter>`, the local IRK is the `IRK` value
+///
+/// ## Example
+/// ```
+/// "IRK": "hex:9e,0c,27,a4,f5,e0,b5,c8,d2,c1,a9,d8,4e,3f,1b,67"
+/// "4c875d26dc9f": "hex:78,6d,c4,33,2d,38,5a,48,c4,e7,18,fe,0b,84,ff,20"
+/// ```
+pub fn adapter(address: String, entries: &HashMap<String, String>) -> uni_bt_device::UniBtAdapter {
+    uni_bt_device::UniBtAdapter {
+        address: KeyAddress(address).into(),
+        irk: entries.get("IRK").map(|irk| Irk(irk.clone()).into()),
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct BtDevice51 {
     /// "AuthReq": "dword:0000002d"
@@ -155,9 +207,150 @@ struct BtDevice51 {
     #[serde(rename = "IRK")]
     pub irk: Option<Irk>,
     /// "CSRK": "hex:fc,ea,f8,3e,e3,ee,ee,d0,96,61,96,2a,6e,b0,33,8a"
+    #[serde(rename = "CSRK")]
     pub csrk: Option<Csrk>,
 }
 
+impl BtDevice51 {
+    /// `Address` with the kind given by `AddressType`, 0 is public and 1 random
+    fn address(&self) -> uni_bt_device::Address {
+        let mut address: uni_bt_device::Address = self.address.clone().into();
+        address.1 = match self
+            .address_type
+            .as_deref()
+            .map(win_reged_helpers::dword_to_u32)
+        {
+            Some(ADDRESS_TYPE_PUBLIC) => uni_bt_device::AddressType::Public,
+            Some(ADDRESS_TYPE_RANDOM) => uni_bt_device::AddressType::random(&address.0),
+            _ => uni_bt_device::AddressType::Other,
+        };
+        address
+    }
+
+    /// `AuthReq` has the bits of the SMP pairing request, a `KeyLength` of 0 is
+    /// the full 16 bytes
+    ///
+    /// ## Example
+    /// ```
+    /// "AuthReq": "dword:0000002d" -> bonding (0x01), MITM (0x04), SC (0x08), CT2 (0x20)
+    /// "KeyLength": "dword:00000010" -> 16
+    /// ```
+    fn le_security(&self) -> uni_bt_device::LeSecurity {
+        let auth_req = win_reged_helpers::dword_to_u32(&self.auth_req);
+        let key_size = match win_reged_helpers::dword_to_u32(&self.key_length) {
+            0 => MAX_KEY_SIZE,
+            key_length => key_length.min(MAX_KEY_SIZE as u32) as u8,
+        };
+
+        uni_bt_device::LeSecurity {
+            mitm: auth_req & AUTH_REQ_MITM != 0,
+            secure_connections: auth_req & AUTH_REQ_SC != 0,
+            key_size,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct BtDeviceMeta {
+    /// "Name": "hex:57,48,2d,31,30,30,30,58,4d,34,00"
+    #[serde(rename = "Name")]
+    pub name: Option<String>,
+    /// "COD": "dword:00240404"
+    #[serde(rename = "COD")]
+    pub cod: Option<String>,
+    /// "VIDType": "dword:00000001"
+    #[serde(rename = "VIDType")]
+    pub vid_type: Option<String>,
+    /// "VID": "dword:0000054c"
+    #[serde(rename = "VID")]
+    pub vid: Option<String>,
+    /// "PID": "dword:00000d58"
+    #[serde(rename = "PID")]
+    pub pid: Option<String>,
+    /// "Version": "dword:00000100"
+    #[serde(rename = "Version")]
+    pub version: Option<String>,
+    /// "LastConnected": "hex(b):b0,3e,6a,91,e2,1f,da,01"
+    #[serde(rename = "LastConnected")]
+    pub last_connected: Option<String>,
+    /// "LastSeen": "hex(b):b0,3e,6a,91,e2,1f,da,01"
+    #[serde(rename = "LastSeen")]
+    pub last_seen: Option<String>,
+}
+
+impl TryFrom<BtDeviceMeta> for uni_bt_device::DeviceMeta {
+    type Error = String;
+
+    /// Fails with the first value which doesn't have the type windows writes, e.g.
+    /// `"COD"="hex:04,04,24,00"`
+    fn try_from(value: BtDeviceMeta) -> Result<Self, Self::Error> {
+        let dword = |name: &str, dword: Option<&str>| {
+            dword
+                .map(|d| {
+                    win_reged_helpers::parse_dword(d)
+                        .ok_or_else(|| format!("{} is not a dword: {:?}", name, d))
+                })
+                .transpose()
+        };
+        let filetime = |name: &str, hex: Option<&str>| {
+            hex.map(|h| {
+                win_reged_helpers::parse_hex_b(h)
+                    .ok_or_else(|| format!("{} is not a FILETIME: {:?}", name, h))
+            })
+            .transpose()
+            .map(|t| t.and_then(win_reged_helpers::filetime_to_system_time))
+        };
+
+        let name = value
+            .name
+            .as_deref()
+            .map(|n| {
+                win_reged_helpers::hex_to_vec(n).ok_or_else(|| format!("Name is not hex: {:?}", n))
+            })
+            .transpose()?
+            .map(|bytes| {
+                let bytes: Vec<_> = bytes.into_iter().take_while(|b| *b != 0).collect();
+                String::from_utf8_lossy(&bytes).to_string()
+            });
+
+        let vid_type = dword("VIDType", value.vid_type.as_deref())?;
+        let version = dword("Version", value.version.as_deref())?;
+        let device_id = match (
+            dword("VID", value.vid.as_deref())?,
+            dword("PID", value.pid.as_deref())?,
+        ) {
+            (Some(vid), Some(pid)) => Some(uni_bt_device::DeviceId {
+                source: vid_type.map(|t| t as u16).unwrap_or(1),
+                vendor: vid as u16,
+                product: pid as u16,
+                version: version.map(|v| v as u16).unwrap_or(0),
+            }),
+            _ => None,
+        };
+
+        let meta = Self {
+            name,
+            class: dword("COD", value.cod.as_deref())?,
+            device_id,
+            last_connected: filetime("LastConnected", value.last_connected.as_deref())?,
+            last_seen: filetime("LastSeen", value.last_seen.as_deref())?,
+        };
+        debug!("win device meta {:?}", meta);
+        Ok(meta)
+    }
+}
+
+struct LinkKey(String);
+
+impl From<LinkKey> for uni_bt_device::LinkKey {
+    /// "hex:78,6d,c4,33,2d,38,5a,48,c4,e7,18,fe,0b,84,ff,20" -> [u8; 16]
+    fn from(value: LinkKey) -> Self {
+        let arr = win_reged_helpers::hex_to_bytes(&value.0);
+        debug!("win link key {:?} -> {:?}", value.0, arr);
+        Self(arr)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 struct Ltk(String);
 
@@ -199,6 +392,8 @@ struct Address(String);
 
 impl From<Address> for uni_bt_device::Address {
     /// "hex(b):c1,f4,11,0a,29,c8,00,00" -> [u8; 6]
+    ///
+    /// The two high bytes only pad the address to a u64, its kind is in `AddressType`
     fn from(value: Address) -> Self {
         let arr: [u8; 6] = win_reged_helpers::hex_b_to_bytes(&value.0)
             .into_iter()
@@ -208,7 +403,7 @@ impl From<Address> for uni_bt_device::Address {
             .try_into()
             .expect("invalid address length");
         debug!("win address {:?} -> {:?}", value.0, arr);
-        Self(arr)
+        Self(arr, uni_bt_device::AddressType::Public)
     }
 }
 
@@ -253,11 +448,109 @@ impl From<KeyAddress> for uni_bt_device::Address {
             .collect();
         let arr: [u8; 6] = bytes.try_into().expect("invalid mac address length");
         debug!("win mac {:?} -> {:?}", value.0, arr);
-        Self(arr)
+        Self(arr, uni_bt_device::AddressType::Public)
     }
 }
 
+/// `.reg` file which `reged -I` imports, with keys of the devices under `keys_path`.
+/// Classic devices are values of the adapter key, LE devices are its subkeys
+///
+/// ## Example
+/// ```
+/// Windows Registry Editor Version 5.00
+///
+/// [HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Services\BTHPORT\Parameters\Keys\c0fbf9601c13]
+/// "4c875d26dc9f"=hex:78,6d,c4,33,2d,38,5a,48,c4,e7,18,fe,0b,84,ff,20
+///
+/// [HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Services\BTHPORT\Parameters\Keys\c0fbf9601c13\e7a1f2c3d4b5]
+/// "LTK"=hex:c2,90,19,3b,1e,be,c7,d0,18,c6,4f,e9,67,ad,6b,d5
+/// "ERand"=hex(b):2c,0a,8e,5b,d4,91,76,8e
+/// "EDIV"=dword:0000a3f1
+/// ```
+pub fn reg_file(keys_path: &str, devices: &[uni_bt_device::UniBtDevice]) -> String {
+    let mut reg = String::from("Windows Registry Editor Version 5.00\n");
+
+    let mut adapters: Vec<&uni_bt_device::Address> = vec![];
+    for device in devices {
+        if !adapters.iter().any(|a| a.0 == device.parent_address.0) {
+            adapters.push(&device.parent_address);
+        }
+    }
+
+    for adapter in adapters {
+        let adapter_path = format!(
+            "{}\\{}",
+            keys_path,
+            win_reged_helpers::bytes_to_key_address(&adapter.0)
+        );
+        let adapter_devices: Vec<_> = devices
+            .iter()
+            .filter(|d| d.parent_address.0 == adapter.0)
+            .collect();
+
+        let classic: Vec<_> = adapter_devices
+            .iter()
+            .filter_map(|d| d.link_key.as_ref().map(|k| (&d.address, k)))
+            .collect();
+        if !classic.is_empty() {
+            reg.push_str(&format!("\n[{}]\n", adapter_path));
+            for (address, link_key) in classic {
+                reg.push_str(&format!(
+                    "\"{}\"={}\n",
+                    win_reged_helpers::bytes_to_key_address(&address.0),
+                    win_reged_helpers::bytes_to_hex(&link_key.0)
+                ));
+            }
+        }
+
+        for device in adapter_devices.iter().filter(|d| d.ltk.is_some()) {
+            reg.push_str(&format!(
+                "\n[{}\\{}]\n",
+                adapter_path,
+                win_reged_helpers::bytes_to_key_address(&device.address.0)
+            ));
+            if let Some(ltk) = device.ltk.as_ref() {
+                reg.push_str(&format!(
+                    "\"LTK\"={}\n",
+                    win_reged_helpers::bytes_to_hex(&ltk.0)
+                ));
+            }
+            if let Some(e_rand) = device.e_rand.as_ref() {
+                reg.push_str(&format!(
+                    "\"ERand\"={}\n",
+                    win_reged_helpers::bytes_to_hex_b(&e_rand.0)
+                ));
+            }
+            if let Some(e_div) = device.e_div.as_ref() {
+                reg.push_str(&format!(
+                    "\"EDIV\"=dword:{:08x}\n",
+                    u32::from_le_bytes(e_div.0)
+                ));
+            }
+            if let Some(irk) = device.irk.as_ref() {
+                reg.push_str(&format!(
+                    "\"IRK\"={}\n",
+                    win_reged_helpers::bytes_to_hex(&irk.0)
+                ));
+            }
+            if let Some(csrk) = device.csrk.as_ref() {
+                reg.push_str(&format!(
+                    "\"CSRK\"={}\n",
+                    win_reged_helpers::bytes_to_hex(&csrk.0)
+                ));
+            }
+        }
+    }
+
+    reg
+}
+
 mod win_reged_helpers {
+    use super::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Seconds between 1601-01-01 (FILETIME epoch) and 1970-01-01
+    const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
     /// "hex:fc,ea,f8,3e,e3,ee,ee,d0,96,61,96,2a,6e,b0,33,8a" -> [u8; 16]
     pub fn hex_to_bytes(hex: &str) -> [u8; 16] {
         let bytes: Vec<_> = hex[4..]
@@ -270,17 +563,122 @@ mod win_reged_helpers {
 
     /// "hex(b):00,00,00,00,00,00,00,00" -> [u8; 8]
     pub fn hex_b_to_bytes(hex: &str) -> [u8; 8] {
-        let bytes: Vec<_> = hex[7..]
+        parse_hex_b(hex).expect("invalid hex(b) value")
+    }
+
+    /// "hex(b):00,00,00,00,00,00,00,00" -> Some([u8; 8]), `None` for any other value
+    pub fn parse_hex_b(hex: &str) -> Option<[u8; 8]> {
+        let bytes = hex
+            .strip_prefix("hex(b):")?
             .split(',')
-            .map(|s| u8::from_str_radix(s, 16).expect("invalid hex digit"))
-            .collect();
-        let arr: [u8; 8] = bytes.as_slice().try_into().expect("invalid bytes length");
-        arr
+            .map(|s| u8::from_str_radix(s, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        bytes.as_slice().try_into().ok()
     }
 
+    /// "dword:0000a3f1" -> [0xf1, 0xa3, 0x00, 0x00]
     pub fn dword_to_bytes(dword: &str) -> [u8; 4] {
-        let num = u32::from_str_radix(&dword[6..], 10).expect("invalid decimal number");
-        let arr: [u8; 4] = num.to_ne_bytes();
-        arr
+        dword_to_u32(dword).to_le_bytes()
+    }
+
+    /// "hex:57,48,2d,31,00" -> Some(vec![0x57, 0x48, 0x2d, 0x31, 0x00]), "hex:" -> Some(vec![])
+    pub fn hex_to_vec(hex: &str) -> Option<Vec<u8>> {
+        hex.strip_prefix("hex:")?
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| u8::from_str_radix(s, 16).ok())
+            .collect()
+    }
+
+    /// [0xfc, 0xea, 0xf8, ...] -> "hex:fc,ea,f8,..."
+    pub fn bytes_to_hex(bytes: &[u8]) -> String {
+        let hex: Vec<_> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("hex:{}", hex.join(","))
+    }
+
+    /// [0x2c, 0x0a, ...] -> "hex(b):2c,0a,..."
+    pub fn bytes_to_hex_b(bytes: &[u8]) -> String {
+        let hex: Vec<_> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("hex(b):{}", hex.join(","))
+    }
+
+    /// [0xc0, 0xfb, 0xf9, 0x60, 0x1c, 0x13] -> "c0fbf9601c13"
+    pub fn bytes_to_key_address(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// "dword:00240404" -> 0x240404
+    pub fn dword_to_u32(dword: &str) -> u32 {
+        parse_dword(dword).expect("invalid hex number")
+    }
+
+    /// "dword:00240404" -> Some(0x240404), `None` for any other value
+    pub fn parse_dword(dword: &str) -> Option<u32> {
+        u32::from_str_radix(dword.strip_prefix("dword:")?, 16).ok()
+    }
+
+    /// Bytes of "hex(b):b0,3e,6a,91,e2,1f,da,01" -> 2023-11-25 21:01:32 UTC
+    ///
+    /// FILETIME is the number of 100ns intervals since 1601-01-01, zero means never
+    pub fn filetime_to_system_time(filetime: [u8; 8]) -> Option<SystemTime> {
+        let intervals = u64::from_le_bytes(filetime);
+        let secs = (intervals / 10_000_000).checked_sub(FILETIME_UNIX_OFFSET)?;
+        Some(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::win_reged_helpers::*;
+    use super::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parses_registry_values() {
+        assert_eq!(
+            hex_to_bytes("hex:fc,ea,f8,3e,e3,ee,ee,d0,96,61,96,2a,6e,b0,33,8a"),
+            [
+                0xfc, 0xea, 0xf8, 0x3e, 0xe3, 0xee, 0xee, 0xd0, 0x96, 0x61, 0x96, 0x2a, 0x6e, 0xb0,
+                0x33, 0x8a
+            ]
+        );
+        assert_eq!(
+            parse_hex_b("hex(b):2c,0a,00,00,00,00,00,ff"),
+            Some([0x2c, 0x0a, 0, 0, 0, 0, 0, 0xff])
+        );
+        assert_eq!(parse_hex_b("hex:2c,0a,00,00,00,00,00,ff"), None);
+        assert_eq!(parse_hex_b("hex(b):2c,0a"), None);
+        assert_eq!(dword_to_bytes("dword:0000a3f1"), [0xf1, 0xa3, 0x00, 0x00]);
+        assert_eq!(parse_dword("dword:00240404"), Some(0x240404));
+        assert_eq!(parse_dword("hex:04,04,24,00"), None);
+        assert_eq!(
+            hex_to_vec("hex:57,48,2d,31,00"),
+            Some(vec![0x57, 0x48, 0x2d, 0x31, 0x00])
+        );
+        assert_eq!(hex_to_vec("hex:"), Some(vec![]));
+        assert_eq!(hex_to_vec("hex:zz"), None);
+    }
+
+    #[test]
+    fn formats_registry_values() {
+        assert_eq!(bytes_to_hex(&[0xfc, 0xea, 0x08]), "hex:fc,ea,08");
+        assert_eq!(bytes_to_hex_b(&[0x2c, 0x0a]), "hex(b):2c,0a");
+        assert_eq!(
+            bytes_to_key_address(&[0xc0, 0xfb, 0xf9, 0x60, 0x1c, 0x13]),
+            "c0fbf9601c13"
+        );
+
+        let bytes = [
+            0x78, 0x6d, 0xc4, 0x33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0x20,
+        ];
+        assert_eq!(hex_to_bytes(&bytes_to_hex(&bytes)), bytes);
+    }
+
+    #[test]
+    fn converts_filetimes() {
+        assert_eq!(
+            filetime_to_system_time(parse_hex_b("hex(b):b0,3e,6a,91,e2,1f,da,01").unwrap()),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_946_092))
+        );
+        assert_eq!(filetime_to_system_time([0; 8]), None, "never");
     }
 }
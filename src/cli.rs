@@ -1,20 +1,55 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::pipeline::ConflictPolicy;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Optional name to operate on
-    pub name: Option<String>,
-
     /// Sets a custom config file
     #[arg(short, long, value_name = "FILE")]
     pub config: Option<PathBuf>,
 
-    /// Turn debugging information on
-    #[arg(short, long)]
-    pub verbose: bool,
+    /// Print more, `-v` for what is being done, `-vv` for debugging information and
+    /// `-vvv` for everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Print only errors, the log file and the journal still get info and above. Can't
+    /// be combined with `-v`
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also append the log to FILE, with fields like DEVICE=4C:87:5D:26:DC:9F
+    #[arg(long, global = true, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Also send the log to journald with DEVICE, ADAPTER and ACTION fields
+    #[arg(long, global = true)]
+    pub journal: bool,
+
+    /// Reads windows keys from a saved export instead of a mounted partition, made with
+    /// `reged -x SYSTEM HKEY_LOCAL_MACHINE\SYSTEM ControlSet001\Services\BTHPORT\Parameters FILE`
+    #[arg(long, global = true, value_name = "FILE")]
+    pub reg_export: Option<PathBuf>,
+
+    /// Never prompt, fail when a choice is needed. Implied when stdin is not a terminal
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Answer yes to every confirmation
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Run again through sudo, or pkexec without a terminal, when the command needs
+    /// root. Without it such commands fail with a hint
+    #[arg(long, global = true)]
+    pub elevate: bool,
+
+    /// Partition to take keys from as a mount point, UUID or label, overrides the config
+    #[arg(long, global = true, value_name = "PARTITION")]
+    pub partition: Option<String>,
 
     #[command(subcommand)]
     pub command: Option<Commands>,
@@ -22,10 +57,163 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// does testing things
-    Test {
-        /// lists test values
-        #[arg(short, long)]
-        list: bool,
-    },
-}
\ No newline at end of file
+    /// Copies pairing keys from windows into linux (default)
+    ///
+    /// Exits with 0 when devices were updated, 1 on errors or when every device failed,
+    /// 2 when some devices failed and 3 when there was nothing to update.
+    Sync(SyncArgs),
+    /// Compares windows and linux pairing state per device without changing anything
+    ///
+    /// Exits with 0 when every device is in sync, 2 when any device differs
+    /// and 1 on errors.
+    Status,
+    /// Checks that info files written by the last sync from windows still hold its keys
+    ///
+    /// Exits with 0 when nothing drifted, 2 when any device drifted and 1 on errors.
+    Verify(VerifyArgs),
+    /// Full-screen view of devices paired in windows and linux, to pick a direction per
+    /// device, preview the changes and apply them
+    ///
+    /// Exits like `sync`, with 3 when nothing was applied.
+    Tui(TuiArgs),
+    /// Lists devices paired in windows with their names and other metadata
+    List,
+    /// Shows every change made by earlier syncs, oldest first
+    History(HistoryArgs),
+    /// Saves pairing keys of a system into a JSON file, `sync --source json` reads it
+    ///
+    /// The file holds the keys themselves and is created readable only by its owner.
+    Export(ExportArgs),
+    /// Installs a systemd unit which syncs at every boot before bluetooth.service starts
+    ///
+    /// The unit runs `sync --non-interactive --yes`, so with several windows partitions
+    /// `Partition` has to be set in the config. It logs to the journal, see
+    /// `journalctl -u bt-dualboot-sync ACTION=updated`.
+    InstallService,
+    /// Disables and removes the unit installed by `install-service`
+    UninstallService,
+}
+
+#[derive(Args, Default)]
+pub struct SyncArgs {
+    /// Operating system to take the pairing keys from
+    #[arg(long, value_enum, default_value_t)]
+    pub source: Source,
+
+    /// Operating system to write the pairing keys into, windows with `--source linux`
+    /// and linux otherwise
+    #[arg(long, value_enum)]
+    pub target: Option<Target>,
+
+    /// Also fill name, class and device id of linux devices from windows
+    #[arg(long)]
+    pub fill_metadata: bool,
+
+    /// Root of a linux installation to update, e.g. `/mnt/fedora`, can be repeated.
+    /// Detected installations are offered for choice when omitted. With `--source linux`
+    /// the first one is where the keys are taken from, with `--target windows` the one
+    /// whose ledger records the changes
+    #[arg(long, value_name = "DIR")]
+    pub root: Vec<PathBuf>,
+
+    /// File saved with `export` to take the keys from with `--source json`, stdin
+    /// when omitted or `-`
+    #[arg(long, value_name = "FILE")]
+    pub input: Option<PathBuf>,
+
+    /// Overwrite pairings of the target even when they were used after the source ones,
+    /// same as `--conflict prefer-source` for every device
+    #[arg(long)]
+    pub force: bool,
+
+    /// What to do with devices whose keys differ on both sides: prefer-source,
+    /// prefer-target, newest, ask or skip. Overrides `Policy` of `[Conflicts]` in the
+    /// config, `newest` when neither is set
+    #[arg(long, value_name = "POLICY")]
+    pub conflict: Option<ConflictPolicy>,
+
+    /// Format of the summary printed at the end
+    #[arg(long, value_enum, default_value_t)]
+    pub output: Output,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Operating system to take the pairing keys from
+    #[arg(long, value_enum, default_value_t)]
+    pub source: Source,
+
+    /// Root of the linux installation to take the keys from with `--source linux`,
+    /// `/` when omitted
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
+    /// Where to save the keys, `-` is stdout
+    #[arg(value_name = "FILE", default_value = "-")]
+    pub file: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Root of the linux installation to check, `/` when omitted
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct TuiArgs {
+    /// Root of the linux installation to show, `/` when omitted
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// Root of the linux installation whose ledger is shown, `/` when omitted
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
+    /// Only changes of this device, e.g. `4C:87:5D:26:DC:9F`
+    #[arg(long, value_name = "ADDRESS")]
+    pub device: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum Source {
+    /// Registry of a mounted windows partition
+    #[default]
+    Windows,
+    /// com.apple.Bluetooth.plist of a mounted macos volume
+    Macos,
+    /// Pairings of the linux installation given with `--root`, or the running one
+    Linux,
+    /// Keys saved with `export`, read from `--input`
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// Info files of the linux installations given with `--root`, or detected ones
+    Linux,
+    /// Registry of a mounted windows partition
+    Windows,
+}
+
+impl SyncArgs {
+    /// `--target`, or where keys of `--source` go by default
+    pub fn target(&self) -> Target {
+        self.target.unwrap_or(match self.source {
+            Source::Linux => Target::Windows,
+            _ => Target::Linux,
+        })
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum Output {
+    /// One line per device, for people
+    #[default]
+    Table,
+    /// Whole report as a JSON document, for scripts
+    Json,
+}
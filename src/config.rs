@@ -0,0 +1,102 @@
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::{hooks::Hooks, pipeline::ConflictPolicy, utils::is_valid_linux_address, CustomResult};
+
+const DEFAULT_CONFIG_PATH: &str = "/etc/bt-dualboot.conf";
+
+/// Settings which otherwise would be asked for, needed when running unattended
+///
+/// ## Example
+/// ```
+/// [General]
+/// Partition=/mnt/windows
+///
+/// [Hooks]
+/// PreSync=logger "bt-dualboot syncs $BT_DUALBOOT_DEVICES"
+/// PostSync=etckeeper commit "bt-dualboot $BT_DUALBOOT_DIRECTION"
+///
+/// [Conflicts]
+/// Policy=newest
+/// 4C:87:5D:26:DC:9F=prefer-target
+/// ```
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    #[serde(rename = "General")]
+    pub general: Option<General>,
+    #[serde(rename = "Hooks")]
+    pub hooks: Option<HooksSection>,
+    /// `Policy` for every device and device addresses with their own policy
+    #[serde(rename = "Conflicts")]
+    pub conflicts: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct General {
+    /// Mount point of the partition to take keys from
+    #[serde(rename = "Partition")]
+    pub partition: Option<String>,
+}
+
+/// Shell commands run around `sync`, see `hooks::Hooks`
+#[derive(Deserialize, Debug, Default)]
+pub struct HooksSection {
+    /// Run before anything is written, a failure aborts the sync
+    #[serde(rename = "PreSync")]
+    pub pre_sync: Option<String>,
+    #[serde(rename = "PostSync")]
+    pub post_sync: Option<String>,
+}
+
+impl Config {
+    pub fn partition(&self) -> Option<String> {
+        self.general.as_ref().and_then(|g| g.partition.clone())
+    }
+
+    /// Conflict policy for every device, if set, and the ones of single devices
+    pub fn conflict_policies(
+        &self,
+    ) -> CustomResult<(Option<ConflictPolicy>, HashMap<String, ConflictPolicy>)> {
+        let mut policy = None;
+        let mut device_policies = HashMap::new();
+
+        for (key, value) in self.conflicts.iter().flatten() {
+            let value: ConflictPolicy = value.parse().map_err(|e: String| e.into())?;
+            if key == "Policy" {
+                policy = Some(value);
+            } else if is_valid_linux_address(key) {
+                device_policies.insert(key.to_uppercase(), value);
+            } else {
+                return Err(format!(
+                    "unknown key {:?} in [Conflicts], expected Policy or a device address",
+                    key
+                )
+                .into());
+            }
+        }
+
+        Ok((policy, device_policies))
+    }
+
+    pub fn hooks(&self) -> Hooks {
+        Hooks {
+            pre_sync: self.hooks.as_ref().and_then(|h| h.pre_sync.clone()),
+            post_sync: self.hooks.as_ref().and_then(|h| h.post_sync.clone()),
+        }
+    }
+}
+
+/// Reads the config given with `--config`, or the default one if it exists
+pub fn load(path: Option<&Path>) -> CustomResult<Config> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => Path::new(DEFAULT_CONFIG_PATH),
+        None => return Ok(Config::default()),
+    };
+
+    debug!("reading config {:?}", path);
+    let config_str = read_to_string(path).map_err(|e| e.into())?;
+    serde_ini::from_str(&config_str).map_err(|e| e.into())
+}
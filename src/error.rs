@@ -43,4 +43,21 @@ impl Into<CustomError> for serde_ini::de::Error {
     fn into(self) -> CustomError {
         CustomError::SerdeError(self)
     }
-}
\ No newline at end of file
+}
+
+impl Into<CustomError> for plist::Error {
+    fn into(self) -> CustomError {
+        CustomError::BtDualBootError(self.into())
+    }
+}
+impl Into<CustomError> for serde_json::Error {
+    fn into(self) -> CustomError {
+        CustomError::BtDualBootError(self.into())
+    }
+}
+
+impl Into<CustomError> for serde_ini::ser::Error {
+    fn into(self) -> CustomError {
+        CustomError::BtDualBootError(self.into())
+    }
+}
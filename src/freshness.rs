@@ -0,0 +1,55 @@
+use std::{
+    fs::metadata,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use log::debug;
+
+use crate::{bt_device::uni_bt_device::UniBtDevice, LINUX_BT_DIR};
+
+/// Info files written by a sync are a bit younger than its ledger entries,
+/// such writes are not evidence of the device being used in linux
+const OWN_WRITE_SLACK: Duration = Duration::from_secs(60);
+
+/// Latest evidence of a device being used on each side
+pub struct Freshness {
+    /// From the metadata of the device passed to `compare`, windows or another source
+    pub windows: SystemTime,
+    pub linux: SystemTime,
+}
+
+/// Compares `LastConnected` (or `LastSeen`) from windows with modification times of
+/// `info` and `cache/<device>` in linux, `None` when either side has no timestamps
+///
+/// `last_sync` is the time of the latest ledger entry of the device, an `info` file
+/// not modified since then was written by the sync itself and doesn't count.
+pub fn compare(
+    uni_dev: &UniBtDevice,
+    root: &Path,
+    adapter: &str,
+    address: &str,
+    last_sync: Option<SystemTime>,
+) -> Option<Freshness> {
+    let windows = uni_dev
+        .meta
+        .as_ref()
+        .and_then(|m| m.last_connected.or(m.last_seen))?;
+
+    let adapter_dir = root.join(LINUX_BT_DIR).join(adapter);
+    let info_mtime = modified(&adapter_dir.join(address).join("info"))
+        .filter(|mtime| last_sync.is_none_or(|synced| *mtime > synced + OWN_WRITE_SLACK));
+    let cache_mtime = modified(&adapter_dir.join("cache").join(address));
+
+    let linux = info_mtime.into_iter().chain(cache_mtime).max()?;
+    debug!(
+        "{} last used in windows at {:?}, in linux at {:?}",
+        uni_dev.address, windows, linux
+    );
+
+    Some(Freshness { windows, linux })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,799 @@
+use std::{
+    fs::{read, remove_file, rename, set_permissions, write, DirBuilder, OpenOptions, Permissions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use log::{debug, info, warn};
+use tempfile::TempDir;
+
+use crate::{
+    error::CustomError,
+    ntfs,
+    privilege::{self, Access},
+    CustomResult, REG_KEY_BLUETOOTH_PAIRING_KEYS,
+};
+
+const BASE_BLOCK_SIZE: usize = 4096;
+const BASE_BLOCK_SIGNATURE: &[u8; 4] = b"regf";
+const PRIMARY_SEQUENCE_OFFSET: usize = 0x04;
+const SECONDARY_SEQUENCE_OFFSET: usize = 0x08;
+const FILE_TYPE_OFFSET: usize = 0x1c;
+const HIVE_BINS_SIZE_OFFSET: usize = 0x28;
+const CHECKSUM_OFFSET: usize = 0x1fc;
+
+/// Transaction logs of windows 8.1 and newer, older ones use a dirty vector instead
+const FILE_TYPE_LOG_NEW: u32 = 6;
+/// Log entries follow the first 512 bytes of a base block
+const LOG_ENTRIES_OFFSET: usize = 512;
+const LOG_ENTRY_SIGNATURE: &[u8; 4] = b"HvLE";
+const LOG_ENTRY_HEADER_SIZE: usize = 0x28;
+const MARVIN32_SEED: u64 = 0x82EF_4D88_7A4E_55C5;
+
+/// Extension of the copy of the hive changes are made in, next to the original
+/// so replacing it is a rename on the same volume
+const STAGED_EXTENSION: &str = "bt-dualboot";
+
+/// Hive file which `reged` should read, a temporary copy when transaction
+/// logs had to be applied, removed when dropped
+pub struct HiveFile {
+    path: PathBuf,
+    temporary: bool,
+    /// Private directory of a copy with the logs applied, only the owner can read
+    /// the keys in it
+    _dir: Option<TempDir>,
+}
+
+impl HiveFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for HiveFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(e) = remove_file(&self.path) {
+                warn!("can't remove {:?}: {:?}", self.path, e);
+            }
+        }
+    }
+}
+
+/// Applies `<hive>.LOG1` and `<hive>.LOG2` on top of the primary hive file, changes
+/// windows keeps only in the logs until the hive is flushed, e.g. newly paired devices
+pub fn open(hive_path: &Path) -> CustomResult<HiveFile> {
+    let mut hive = read(hive_path).map_err(|e| e.into())?;
+    let base_block = hive
+        .get(..BASE_BLOCK_SIZE)
+        .filter(|b| b.starts_with(BASE_BLOCK_SIGNATURE))
+        .ok_or_else(|| -> CustomError {
+            format!("{:?} is not a registry hive", hive_path).into()
+        })?;
+    let original = HiveFile {
+        path: hive_path.to_path_buf(),
+        temporary: false,
+        _dir: None,
+    };
+
+    // Logs of a clean hive only hold what was already flushed
+    let primary_sequence = le_u32(base_block, PRIMARY_SEQUENCE_OFFSET).unwrap_or(0);
+    let secondary_sequence = le_u32(base_block, SECONDARY_SEQUENCE_OFFSET).unwrap_or(0);
+    if primary_sequence == secondary_sequence {
+        return Ok(original);
+    }
+    info!(
+        "{:?} is dirty (sequence numbers {} and {}), applying transaction logs",
+        hive_path, primary_sequence, secondary_sequence
+    );
+
+    let logs: Vec<_> = ["LOG1", "LOG2"]
+        .iter()
+        .map(|ext| hive_path.with_extension(ext))
+        .filter_map(|log_path| match read(&log_path) {
+            Ok(log) => Some(log_entries(&log_path, &log)),
+            Err(e) => {
+                debug!("no transaction log {:?}: {:?}", log_path, e);
+                None
+            }
+        })
+        .collect();
+
+    // Entries older than the secondary sequence number are already in the hive, newer
+    // ones are applied in order, possibly switching between the two logs
+    let mut sequence = logs
+        .iter()
+        .flatten()
+        .map(|e| e.sequence)
+        .filter(|s| *s >= secondary_sequence)
+        .min()
+        .unwrap_or(secondary_sequence);
+    let mut applied = 0;
+    while let Some(entry) = logs.iter().flatten().find(|e| e.sequence == sequence) {
+        entry.apply(&mut hive);
+        sequence = sequence.wrapping_add(1);
+        applied += 1;
+    }
+
+    if applied == 0 {
+        warn!(
+            "{:?} is dirty but its transaction logs have nothing to apply, recent pairings may be missing",
+            hive_path
+        );
+        return Ok(original);
+    }
+
+    mark_clean(&mut hive, sequence);
+
+    let dir = private_dir()?;
+    let path = dir
+        .path()
+        .join(hive_path.file_name().unwrap_or("SYSTEM".as_ref()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut f| f.write_all(&hive))
+        .map_err(|e| e.into())?;
+    info!(
+        "applied {} transaction log entries of {:?} to {:?}",
+        applied, hive_path, path
+    );
+
+    Ok(HiveFile {
+        path,
+        temporary: false,
+        _dir: Some(dir),
+    })
+}
+
+/// Directory only the current user can enter, removed when dropped
+fn private_dir() -> CustomResult<TempDir> {
+    let dir = tempfile::Builder::new()
+        .prefix("bt-dualboot-")
+        .tempdir()
+        .map_err(|e| e.into())?;
+    set_permissions(dir.path(), Permissions::from_mode(0o700)).map_err(|e| e.into())?;
+    Ok(dir)
+}
+
+/// Imports `reg` into the SYSTEM hive at `hive_path` of the windows partition mounted
+/// at `win_mount`, creating missing keys and values
+///
+/// Changes go to a copy of the hive which replaces the original only after it re-parses
+/// with every imported value, a byte-for-byte copy of the original is kept in `backup_dir`.
+pub fn import(
+    win_mount: &Path,
+    hive_path: &Path,
+    reg: &str,
+    backup_dir: &Path,
+) -> CustomResult<()> {
+    let state = ntfs::volume_state(win_mount);
+    if state.hibernated {
+        return Err(format!(
+            "windows on {:?} is hibernated or was shut down with Fast Startup, it would undo \
+            the changes when it resumes. Restart windows and shut it down from there first",
+            win_mount
+        )
+        .into());
+    }
+    if state.is_unclean() {
+        return Err(format!(
+            "NTFS on {:?} was not cleanly unmounted, boot windows and shut it down first",
+            win_mount
+        )
+        .into());
+    }
+    if ntfs::is_read_only(win_mount) {
+        return Err(format!(
+            "{:?} is mounted read-only, remount it with `mount -o remount,rw {}`",
+            win_mount,
+            win_mount.display()
+        )
+        .into());
+    }
+
+    // The staged copy is created next to the hive
+    let hive_dir = hive_path.parent().unwrap_or(win_mount);
+    privilege::check(&[
+        (hive_path.to_path_buf(), Access::Write),
+        (hive_dir.to_path_buf(), Access::Write),
+    ])?;
+
+    let original = read(hive_path).map_err(|e| e.into())?;
+    let sequence = clean_sequence(hive_path, &original)?;
+
+    let mut staged = HiveFile {
+        path: hive_path.with_extension(STAGED_EXTENSION),
+        temporary: true,
+        _dir: None,
+    };
+    write(staged.path(), &original).map_err(|e| e.into())?;
+    run_import(staged.path(), reg)?;
+
+    let next = sequence.wrapping_add(1);
+    seal(staged.path(), next)?;
+    check_values(staged.path(), reg)?;
+    replace(&mut staged, hive_path, &original, backup_dir)?;
+    info!("updated {:?}, sequence number {}", hive_path, next);
+
+    Ok(())
+}
+
+/// Marks the staged hive clean with `sequence`, reged leaves the base block as it
+/// was and windows has to see a newer clean hive
+fn seal(staged_path: &Path, sequence: u32) -> CustomResult<()> {
+    let mut hive = read(staged_path).map_err(|e| e.into())?;
+    check_structure(staged_path, &hive)?;
+    mark_clean(&mut hive, sequence);
+    write(staged_path, &hive).map_err(|e| e.into())?;
+
+    check_structure(staged_path, &read(staged_path).map_err(|e| e.into())?)
+}
+
+/// Backs up `original` to `backup_dir`, then renames the staged hive over `hive_path`
+fn replace(
+    staged: &mut HiveFile,
+    hive_path: &Path,
+    original: &[u8],
+    backup_dir: &Path,
+) -> CustomResult<()> {
+    // The hive holds every pairing key of windows, only root may read the backup
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(backup_dir)
+        .map_err(|e| e.into())?;
+    let backup_path = backup_dir.join(hive_path.file_name().unwrap_or_default());
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&backup_path)
+        .and_then(|mut f| f.write_all(original))
+        .map_err(|e| e.into())?;
+    if read(&backup_path).map_err(|e| e.into())? != original {
+        return Err(format!("backup {:?} differs from {:?}", backup_path, hive_path).into());
+    }
+    debug!("backed up {:?} to {:?}", hive_path, backup_path);
+
+    rename(staged.path(), hive_path).map_err(|e| e.into())?;
+    staged.temporary = false;
+    Ok(())
+}
+
+/// Sequence number of a hive with nothing left in its transaction logs, windows
+/// would replay the logs over changes made to a dirty hive
+fn clean_sequence(hive_path: &Path, hive: &[u8]) -> CustomResult<u32> {
+    check_structure(hive_path, hive)?;
+
+    let primary_sequence = le_u32(hive, PRIMARY_SEQUENCE_OFFSET).unwrap_or(0);
+    let secondary_sequence = le_u32(hive, SECONDARY_SEQUENCE_OFFSET).unwrap_or(0);
+    if primary_sequence != secondary_sequence {
+        return Err(format!(
+            "{:?} has changes only in its transaction logs, boot windows and shut it down first",
+            hive_path
+        )
+        .into());
+    }
+    Ok(primary_sequence)
+}
+
+/// Signature, checksum and size of the hive bins of a hive
+fn check_structure(hive_path: &Path, hive: &[u8]) -> CustomResult<()> {
+    let valid = hive.len() >= BASE_BLOCK_SIZE
+        && hive.starts_with(BASE_BLOCK_SIGNATURE)
+        && le_u32(hive, CHECKSUM_OFFSET) == Some(base_block_checksum(hive))
+        && le_u32(hive, HIVE_BINS_SIZE_OFFSET)
+            .is_some_and(|size| BASE_BLOCK_SIZE + size as usize <= hive.len());
+
+    if !valid {
+        return Err(format!("{:?} is not a valid registry hive", hive_path).into());
+    }
+    Ok(())
+}
+
+/// Sequence numbers of a hive whose base block is up to date, with its checksum
+fn mark_clean(hive: &mut [u8], sequence: u32) {
+    set_le_u32(hive, PRIMARY_SEQUENCE_OFFSET, sequence);
+    set_le_u32(hive, SECONDARY_SEQUENCE_OFFSET, sequence);
+    update_checksum(hive);
+}
+
+fn run_import(hive_path: &Path, reg: &str) -> CustomResult<()> {
+    // reged reads the keys from a file, kept away from the windows partition
+    let dir = private_dir()?;
+    let reg_path = dir.path().join("bt-dualboot.reg");
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&reg_path)
+        .and_then(|mut f| f.write_all(reg.as_bytes()))
+        .map_err(|e| e.into())?;
+    debug!("importing {:?} into {:?}", reg_path, hive_path);
+
+    let status = Command::new("reged")
+        .args([
+            "-I",
+            "-C",
+            hive_path.to_str().expect("path of the hive is utf-8"),
+            r"HKEY_LOCAL_MACHINE\SYSTEM",
+            reg_path
+                .to_str()
+                .expect("path of the temporary dir is utf-8"),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status();
+
+    let status = status.map_err(|e| e.into())?;
+    if !status.success() {
+        return Err(format!("reged failed to import into {:?}: {}", hive_path, status).into());
+    }
+    Ok(())
+}
+
+/// Every value of `reg` read back from the hive
+fn check_values(hive_path: &Path, reg: &str) -> CustomResult<()> {
+    let expected = crate::windows::parse_chntpw_export(reg)?;
+    let actual = crate::windows::parse_chntpw_export(&crate::windows::get_chntpw_export(
+        hive_path,
+        REG_KEY_BLUETOOTH_PAIRING_KEYS,
+    )?)?;
+
+    for (key, values) in expected.iter() {
+        for (name, value) in values.iter() {
+            let found = actual.get(key).and_then(|v| v.get(name));
+            if !found.is_some_and(|f| f.eq_ignore_ascii_case(value)) {
+                return Err(format!(
+                    "{}\\{} is {:?} after importing, expected {:?}",
+                    key, name, found, value
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Dirty pages written at once, its sequence number orders entries across both logs
+///
+/// ## Example
+/// ```
+/// 0x00 "HvLE"
+/// 0x04 size of the entry
+/// 0x0c sequence number
+/// 0x10 size of hive bins data after applying
+/// 0x14 number of dirty pages
+/// 0x18 hash of the pages, 0x20 hash of the first 32 bytes
+/// 0x28 (offset, size) of every page, then the pages
+/// ```
+struct LogEntry {
+    sequence: u32,
+    hive_bins_size: u32,
+    /// Offset from the start of hive bins and content of every page
+    pages: Vec<(usize, Vec<u8>)>,
+}
+
+impl LogEntry {
+    fn apply(&self, hive: &mut Vec<u8>) {
+        let size = BASE_BLOCK_SIZE + self.hive_bins_size as usize;
+        hive.resize(size.max(hive.len()), 0);
+        set_le_u32(hive, HIVE_BINS_SIZE_OFFSET, self.hive_bins_size);
+
+        for (offset, page) in self.pages.iter() {
+            let start = BASE_BLOCK_SIZE + offset;
+            if hive.len() < start + page.len() {
+                hive.resize(start + page.len(), 0);
+            }
+            hive[start..start + page.len()].copy_from_slice(page);
+        }
+        hive.truncate(size);
+    }
+}
+
+/// Valid entries of a new format transaction log, reading stops at the first
+/// entry whose hashes don't match as that's where windows stopped writing
+fn log_entries(log_path: &Path, log: &[u8]) -> Vec<LogEntry> {
+    let file_type = log
+        .get(..BASE_BLOCK_SIZE.min(log.len()))
+        .filter(|b| b.starts_with(BASE_BLOCK_SIGNATURE))
+        .and_then(|b| le_u32(b, FILE_TYPE_OFFSET));
+    if file_type != Some(FILE_TYPE_LOG_NEW) {
+        debug!(
+            "{:?} is not a transaction log of windows 8.1 or newer",
+            log_path
+        );
+        return vec![];
+    }
+
+    let mut entries = vec![];
+    let mut offset = LOG_ENTRIES_OFFSET;
+    while let Some(entry) = log.get(offset..).and_then(parse_log_entry) {
+        offset += entry.0;
+        entries.push(entry.1);
+    }
+    debug!("{:?} has {} valid entries", log_path, entries.len());
+
+    entries
+}
+
+/// Size and content of the log entry at the start of `bytes`
+fn parse_log_entry(bytes: &[u8]) -> Option<(usize, LogEntry)> {
+    if !bytes.starts_with(LOG_ENTRY_SIGNATURE) {
+        return None;
+    }
+
+    let size = le_u32(bytes, 0x04)? as usize;
+    let entry = bytes
+        .get(..size)
+        .filter(|_| size >= LOG_ENTRY_HEADER_SIZE)?;
+    if le_u64(entry, 0x20)? != marvin32(&entry[..0x20])
+        || le_u64(entry, 0x18)? != marvin32(&entry[LOG_ENTRY_HEADER_SIZE..])
+    {
+        return None;
+    }
+
+    let page_count = le_u32(entry, 0x14)? as usize;
+    let mut data_offset = LOG_ENTRY_HEADER_SIZE + page_count * 8;
+    let mut pages = vec![];
+    for i in 0..page_count {
+        let page_offset = le_u32(entry, LOG_ENTRY_HEADER_SIZE + i * 8)? as usize;
+        let page_size = le_u32(entry, LOG_ENTRY_HEADER_SIZE + i * 8 + 4)? as usize;
+        let page = entry.get(data_offset..data_offset + page_size)?;
+        pages.push((page_offset, page.to_vec()));
+        data_offset += page_size;
+    }
+
+    Some((
+        size,
+        LogEntry {
+            sequence: le_u32(entry, 0x0c)?,
+            hive_bins_size: le_u32(entry, 0x10)?,
+            pages,
+        },
+    ))
+}
+
+/// XOR of the first 127 dwords of the base block, 0 and -1 are not allowed
+pub fn update_checksum(hive: &mut [u8]) {
+    let checksum = base_block_checksum(hive);
+    set_le_u32(hive, CHECKSUM_OFFSET, checksum);
+}
+
+fn base_block_checksum(hive: &[u8]) -> u32 {
+    let checksum = hive[..CHECKSUM_OFFSET]
+        .chunks_exact(4)
+        .map(|dword| u32::from_le_bytes([dword[0], dword[1], dword[2], dword[3]]))
+        .fold(0, |checksum, dword| checksum ^ dword);
+
+    match checksum {
+        0 => 1,
+        0xffff_ffff => 0xffff_fffe,
+        checksum => checksum,
+    }
+}
+
+/// Marvin32 with the seed the registry uses for log entries
+fn marvin32(data: &[u8]) -> u64 {
+    marvin32_seeded(MARVIN32_SEED, data)
+}
+
+/// Marvin32 of `data`, both 32 bit halves of the state are kept
+fn marvin32_seeded(seed: u64, data: &[u8]) -> u64 {
+    let mut lo = seed as u32;
+    let mut hi = (seed >> 32) as u32;
+
+    let mut dwords = data.chunks_exact(4);
+    for dword in &mut dwords {
+        marvin32_block(
+            u32::from_le_bytes([dword[0], dword[1], dword[2], dword[3]]),
+            &mut lo,
+            &mut hi,
+        );
+    }
+
+    let tail = dwords.remainder();
+    let last = match tail.len() {
+        0 => 0x80,
+        1 => 0x8000 | tail[0] as u32,
+        2 => 0x80_0000 | u16::from_le_bytes([tail[0], tail[1]]) as u32,
+        _ => 0x8000_0000 | u16::from_le_bytes([tail[0], tail[1]]) as u32 | (tail[2] as u32) << 16,
+    };
+    marvin32_block(last, &mut lo, &mut hi);
+    marvin32_block(0, &mut lo, &mut hi);
+
+    ((hi as u64) << 32) | lo as u64
+}
+
+fn marvin32_block(value: u32, lo: &mut u32, hi: &mut u32) {
+    *lo = lo.wrapping_add(value);
+    *hi ^= *lo;
+    *lo = lo.rotate_left(20).wrapping_add(*hi);
+    *hi = hi.rotate_left(9) ^ *lo;
+    *lo = lo.rotate_left(27).wrapping_add(*hi);
+    *hi = hi.rotate_left(19);
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn set_le_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Base block of a hive with `hive_bins_size` bytes of hive bins
+    fn base_block(primary: u32, secondary: u32, file_type: u32, hive_bins_size: u32) -> Vec<u8> {
+        let mut block = vec![0u8; BASE_BLOCK_SIZE];
+        block[..4].copy_from_slice(BASE_BLOCK_SIGNATURE);
+        set_le_u32(&mut block, PRIMARY_SEQUENCE_OFFSET, primary);
+        set_le_u32(&mut block, SECONDARY_SEQUENCE_OFFSET, secondary);
+        set_le_u32(&mut block, FILE_TYPE_OFFSET, file_type);
+        set_le_u32(&mut block, HIVE_BINS_SIZE_OFFSET, hive_bins_size);
+        update_checksum(&mut block);
+        block
+    }
+
+    /// Log entry writing each of `pages` at its offset from the start of hive bins
+    fn log_entry(sequence: u32, hive_bins_size: u32, pages: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut entry = vec![0u8; LOG_ENTRY_HEADER_SIZE];
+        entry[..4].copy_from_slice(LOG_ENTRY_SIGNATURE);
+        set_le_u32(&mut entry, 0x0c, sequence);
+        set_le_u32(&mut entry, 0x10, hive_bins_size);
+        set_le_u32(&mut entry, 0x14, pages.len() as u32);
+        for (offset, page) in pages {
+            entry.extend_from_slice(&offset.to_le_bytes());
+            entry.extend_from_slice(&(page.len() as u32).to_le_bytes());
+        }
+        for (_, page) in pages {
+            entry.extend_from_slice(page);
+        }
+
+        let size = entry.len() as u32;
+        set_le_u32(&mut entry, 0x04, size);
+        let data_hash = marvin32(&entry[LOG_ENTRY_HEADER_SIZE..]);
+        entry[0x18..0x20].copy_from_slice(&data_hash.to_le_bytes());
+        let header_hash = marvin32(&entry[..0x20]);
+        entry[0x20..0x28].copy_from_slice(&header_hash.to_le_bytes());
+        entry
+    }
+
+    fn log(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut log = base_block(0, 0, FILE_TYPE_LOG_NEW, 0);
+        log.truncate(LOG_ENTRIES_OFFSET);
+        log.extend(entries.concat());
+        log
+    }
+
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("hex digit"))
+            .collect()
+    }
+
+    #[test]
+    fn hashes_with_marvin32() {
+        // Test vectors of the reference implementation
+        let seed = 0x004f_b61a_001b_dbcc;
+        for (data, hash) in [
+            ("af", 0x48e7_3fc7_7d75_ddc1),
+            ("e70f", 0xb5f6_e1fc_485d_bff8),
+            ("37f495", 0xf0b0_7c78_9b8c_f7e8),
+            ("153fb79826", 0xe6c0_8c6d_a2af_a997),
+            ("0932e6246c47", 0x6f04_bf1a_5ea2_4060),
+            ("ab427ea8d10fc7", 0xe118_47e4_f067_8c41),
+        ] {
+            assert_eq!(marvin32_seeded(seed, &hex(data)), hash, "{}", data);
+        }
+    }
+
+    #[test]
+    fn parses_log_entries() {
+        let entry = log_entry(7, 8192, &[(4096, b"hbin"), (0, b"abc")]);
+        let (size, parsed) = parse_log_entry(&entry).expect("valid entry");
+        assert_eq!(size, entry.len());
+        assert_eq!(parsed.sequence, 7);
+        assert_eq!(parsed.hive_bins_size, 8192);
+        assert_eq!(
+            parsed.pages,
+            vec![(4096, b"hbin".to_vec()), (0, b"abc".to_vec())]
+        );
+
+        // windows stopped writing halfway
+        let mut torn = entry.clone();
+        *torn.last_mut().unwrap() ^= 0xff;
+        assert!(parse_log_entry(&torn).is_none());
+        let mut header = entry.clone();
+        header[0x0c] = 8;
+        assert!(parse_log_entry(&header).is_none());
+        assert!(parse_log_entry(&entry[..entry.len() - 1]).is_none());
+        assert!(parse_log_entry(&[0u8; 64]).is_none());
+    }
+
+    #[test]
+    fn replays_logs_of_dirty_hives() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let hive_path = dir.path().join("SYSTEM");
+        let mut hive = base_block(6, 4, 0, 4096);
+        hive.resize(BASE_BLOCK_SIZE + 4096, 0);
+        write(&hive_path, &hive).expect("write hive");
+
+        // 3 is already in the hive, 4 and 5 continue it across both logs, 7 is past a gap
+        write(
+            hive_path.with_extension("LOG1"),
+            log(&[
+                log_entry(3, 4096, &[(0, b"old")]),
+                log_entry(4, 4096, &[(0, b"four")]),
+                log_entry(7, 4096, &[(0, b"seven")]),
+            ]),
+        )
+        .expect("write log");
+        write(
+            hive_path.with_extension("LOG2"),
+            log(&[log_entry(5, 8192, &[(4096, b"five")])]),
+        )
+        .expect("write log");
+
+        let replayed = open(&hive_path).expect("replayed hive");
+        let copy = replayed.path().to_path_buf();
+        assert_ne!(copy, hive_path);
+        let mode = std::fs::metadata(&copy)
+            .expect("copy exists")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let dir_mode = std::fs::metadata(copy.parent().unwrap())
+            .expect("private dir exists")
+            .permissions()
+            .mode();
+        assert_eq!(dir_mode & 0o077, 0);
+
+        let replayed_hive = read(&copy).expect("readable copy");
+        check_structure(&copy, &replayed_hive).expect("valid hive");
+        assert_eq!(replayed_hive.len(), BASE_BLOCK_SIZE + 8192);
+        assert_eq!(&replayed_hive[BASE_BLOCK_SIZE..][..4], b"four");
+        assert_eq!(&replayed_hive[BASE_BLOCK_SIZE + 4096..][..4], b"five");
+        assert_eq!(le_u32(&replayed_hive, PRIMARY_SEQUENCE_OFFSET), Some(6));
+        assert_eq!(le_u32(&replayed_hive, SECONDARY_SEQUENCE_OFFSET), Some(6));
+
+        drop(replayed);
+        assert!(!copy.exists());
+        assert_eq!(read(&hive_path).expect("readable hive"), hive);
+    }
+
+    #[test]
+    fn stages_keys_as_a_clean_newer_hive() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let hive_path = dir.path().join("SYSTEM");
+        let mut hive = base_block(9, 9, 0, 4096);
+        hive.resize(BASE_BLOCK_SIZE + 4096, 0);
+        hive[BASE_BLOCK_SIZE..][..4].copy_from_slice(b"hbin");
+        write(&hive_path, &hive).expect("write hive");
+
+        let original = read(&hive_path).expect("readable hive");
+        let sequence = clean_sequence(&hive_path, &original).expect("clean hive");
+        assert_eq!(sequence, 9);
+
+        // what import does after reged wrote the keys into the staged copy
+        let mut staged = HiveFile {
+            path: hive_path.with_extension(STAGED_EXTENSION),
+            temporary: true,
+            _dir: None,
+        };
+        write(staged.path(), &original).expect("write staged hive");
+        seal(staged.path(), sequence.wrapping_add(1)).expect("valid staged hive");
+        let backup_dir = dir.path().join("backup");
+        replace(&mut staged, &hive_path, &original, &backup_dir).expect("replaced hive");
+        drop(staged);
+
+        let reparsed = read(&hive_path).expect("readable replaced hive");
+        assert_eq!(clean_sequence(&hive_path, &reparsed).ok(), Some(10));
+        assert_eq!(reparsed[BASE_BLOCK_SIZE..], original[BASE_BLOCK_SIZE..]);
+        assert!(!hive_path.with_extension(STAGED_EXTENSION).exists());
+        let backup_path = backup_dir.join("SYSTEM");
+        assert_eq!(read(&backup_path).expect("readable backup"), original);
+        let mode = std::fs::metadata(&backup_path)
+            .expect("backup metadata")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // an earlier backup is never overwritten, the staged copy is removed instead
+        let mut staged = HiveFile {
+            path: hive_path.with_extension(STAGED_EXTENSION),
+            temporary: true,
+            _dir: None,
+        };
+        write(staged.path(), &reparsed).expect("write staged hive");
+        assert!(replace(&mut staged, &hive_path, &reparsed, &backup_dir).is_err());
+        drop(staged);
+        assert!(!hive_path.with_extension(STAGED_EXTENSION).exists());
+        assert_eq!(read(&backup_path).expect("readable backup"), original);
+
+        let mut stale_checksum = reparsed.clone();
+        set_le_u32(&mut stale_checksum, PRIMARY_SEQUENCE_OFFSET, 11);
+        assert!(check_structure(&hive_path, &stale_checksum).is_err());
+        assert!(check_structure(&hive_path, &reparsed[..BASE_BLOCK_SIZE + 2048]).is_err());
+
+        let mut dirty = reparsed.clone();
+        set_le_u32(&mut dirty, PRIMARY_SEQUENCE_OFFSET, 11);
+        update_checksum(&mut dirty);
+        assert!(clean_sequence(&hive_path, &dirty).is_err());
+    }
+
+    #[test]
+    fn writes_reg_files_of_exported_keys() {
+        let export =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dual-mode/export.reg");
+        let interaction = crate::Interaction {
+            partition: None,
+            interactive: false,
+            assume_yes: false,
+        };
+        let keys = crate::windows::get_reged_bt_devices(Some(&export), &interaction)
+            .expect("readable export");
+        assert!(!keys.devices.is_empty());
+
+        let reg = crate::bt_device::win_bt_device::reg_file(
+            &format!(
+                r"HKEY_LOCAL_MACHINE\SYSTEM\{}",
+                REG_KEY_BLUETOOTH_PAIRING_KEYS
+            ),
+            &keys.devices,
+        );
+        let written = crate::windows::parse_chntpw_export(&reg).expect("parsable reg file");
+        let exported = crate::windows::parse_chntpw_export(
+            &std::fs::read_to_string(&export).expect("readable export"),
+        )
+        .expect("parsable export");
+
+        assert!(!written.is_empty());
+        for (key, values) in written.iter() {
+            for (name, value) in values.iter() {
+                let found = exported.get(key).and_then(|v| v.get(name));
+                assert!(
+                    found.is_some_and(|f| f.eq_ignore_ascii_case(value)),
+                    "{}\\{} is {:?}, exported {:?}",
+                    key,
+                    name,
+                    value,
+                    found
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reads_clean_hives_in_place() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let hive_path = dir.path().join("SYSTEM");
+        let mut hive = base_block(4, 4, 0, 4096);
+        hive.resize(BASE_BLOCK_SIZE + 4096, 0);
+        write(&hive_path, &hive).expect("write hive");
+        write(
+            hive_path.with_extension("LOG1"),
+            log(&[log_entry(4, 4096, &[(0, b"four")])]),
+        )
+        .expect("write log");
+
+        assert_eq!(open(&hive_path).expect("hive").path(), hive_path);
+    }
+}
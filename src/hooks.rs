@@ -0,0 +1,134 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use log::{debug, log, warn, Level};
+use tempfile::NamedTempFile;
+
+use crate::{
+    bt_device::linux_bt_device::BtAddress,
+    pipeline::{SourceKeys, System},
+    report, CustomResult,
+};
+
+/// Commands run by `sh -c` around a sync, from `[Hooks]` of the config
+///
+/// Both get the plan in the environment:
+/// - `BT_DUALBOOT_HOOK`: `pre_sync` or `post_sync`
+/// - `BT_DUALBOOT_DIRECTION`: e.g. `windows-to-linux`
+/// - `BT_DUALBOOT_SOURCE`: mount point, root or file the keys were read from
+/// - `BT_DUALBOOT_ADAPTERS`: adapters of the devices, separated by spaces
+/// - `BT_DUALBOOT_DEVICES`: devices as `ADAPTER/DEVICE`, separated by spaces
+///
+/// `post_sync` also gets `BT_DUALBOOT_SUMMARY`, the path of the JSON summary printed by
+/// `--output json`, and `BT_DUALBOOT_EXIT_CODE`
+#[derive(Default)]
+pub struct Hooks {
+    pub pre_sync: Option<String>,
+    pub post_sync: Option<String>,
+}
+
+impl Hooks {
+    /// A failing `pre_sync` is an error, nothing is written then
+    pub fn pre_sync(&self, keys: &SourceKeys, to: System) -> CustomResult<()> {
+        let Some(command) = self.pre_sync.as_deref() else {
+            return Ok(());
+        };
+
+        run("pre_sync", command, &plan_env(keys, to))
+    }
+
+    /// Keys are already written, so a failing `post_sync` is only logged
+    pub fn post_sync(&self, keys: &SourceKeys, to: System, report: &report::SyncReport) {
+        let Some(command) = self.post_sync.as_deref() else {
+            return;
+        };
+
+        let mut env = plan_env(keys, to);
+        // only readable by us and the hook, removed when dropped after it ran
+        let summary = write_summary(report);
+        match summary.as_ref() {
+            Ok(file) => env.push(("BT_DUALBOOT_SUMMARY", file.path().display().to_string())),
+            Err(e) => warn!("can't write the summary for post_sync: {:?}", e),
+        }
+        env.push(("BT_DUALBOOT_EXIT_CODE", report.exit_code().to_string()));
+        if let Err(e) = run("post_sync", command, &env) {
+            warn!("{:?}", e);
+        }
+    }
+}
+
+/// Summary in a new 0600 file of the temp dir
+fn write_summary(report: &report::SyncReport) -> CustomResult<NamedTempFile> {
+    let json = serde_json::to_string_pretty(report).map_err(|e| e.into())?;
+    let mut file = tempfile::Builder::new()
+        .prefix("bt-dualboot-summary-")
+        .suffix(".json")
+        .tempfile()
+        .map_err(|e| e.into())?;
+    file.write_all(json.as_bytes()).map_err(|e| e.into())?;
+    Ok(file)
+}
+
+fn plan_env(keys: &SourceKeys, to: System) -> Vec<(&'static str, String)> {
+    let mut adapters: Vec<_> = keys
+        .devices
+        .iter()
+        .map(|d| BtAddress::from(d.parent_address.clone()).0)
+        .collect();
+    adapters.sort();
+    adapters.dedup();
+    let devices: Vec<_> = keys
+        .devices
+        .iter()
+        .map(|d| {
+            format!(
+                "{}/{}",
+                BtAddress::from(d.parent_address.clone()).0,
+                BtAddress::from(d.address.clone()).0
+            )
+        })
+        .collect();
+
+    vec![
+        (
+            "BT_DUALBOOT_DIRECTION",
+            format!("{}-to-{}", keys.system, to),
+        ),
+        ("BT_DUALBOOT_SOURCE", keys.source.clone()),
+        ("BT_DUALBOOT_ADAPTERS", adapters.join(" ")),
+        ("BT_DUALBOOT_DEVICES", devices.join(" ")),
+    ]
+}
+
+/// Output of hooks goes to the log, stdout is kept for the summary. Without `-v` only
+/// output of failed hooks is shown
+fn run(hook: &str, command: &str, env: &[(&'static str, String)]) -> CustomResult<()> {
+    debug!("running {} hook {:?}", hook, command);
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("BT_DUALBOOT_HOOK", hook)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| e.into())?;
+
+    let level = if output.status.success() {
+        Level::Info
+    } else {
+        Level::Warn
+    };
+    for line in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+    {
+        log!(level, "{}: {}", hook, line);
+    }
+
+    if !output.status.success() {
+        return Err(format!("{} hook {:?} failed with {}", hook, command, output.status).into());
+    }
+    Ok(())
+}
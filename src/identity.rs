@@ -0,0 +1,123 @@
+use std::{
+    fs::{copy, create_dir_all, read_dir, read_to_string, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    bt_device::{
+        linux_bt_device::{linux_hex_key_to_bytes, AdapterIdentity, BtAddress},
+        uni_bt_device::{AddressType, Irk, UniBtAdapter},
+    },
+    confirm,
+    ledger::SectionChange,
+    pipeline::System,
+    utils::{fingerprint, is_valid_linux_address},
+    CustomResult, Interaction, LINUX_BT_DIR,
+};
+
+/// Bluez keeps the local IRK of every adapter next to its paired devices
+const IDENTITY_FILE: &str = "identity";
+
+/// Adapters of the linux installation at `root` with their local IRK, adapters
+/// without an identity file have none
+pub fn read(root: &Path) -> CustomResult<Vec<UniBtAdapter>> {
+    let mut adapters = vec![];
+
+    for adapter in read_dir(root.join(LINUX_BT_DIR)).map_err(|e| e.into())? {
+        let adapter = adapter.map_err(|e| e.into())?;
+        let adapter_name = BtAddress(adapter.file_name().to_string_lossy().to_string());
+        if !is_valid_linux_address(&adapter_name.0) || !adapter.path().is_dir() {
+            continue;
+        }
+        let Some(address) = adapter_name.to_uni(AddressType::Public) else {
+            continue;
+        };
+
+        let path = adapter.path().join(IDENTITY_FILE);
+        let irk = read_to_string(&path)
+            .ok()
+            .and_then(|identity| serde_ini::from_str::<AdapterIdentity>(&identity).ok())
+            .and_then(|identity| linux_hex_key_to_bytes(identity.key()))
+            .map(Irk);
+        adapters.push(UniBtAdapter { address, irk });
+    }
+
+    Ok(adapters)
+}
+
+/// Replaces `<adapter>/identity` of the linux installation at `root` with the local
+/// IRK of `adapter`, the old file is kept in `backup_dir/<adapter>`. Returns the
+/// change of the IRK when the file was written
+///
+/// LE devices using privacy keep the IRK of the host they were paired with, a host
+/// with another IRK looks like a different computer and they refuse to reconnect.
+pub fn update(
+    root: &Path,
+    adapter: &UniBtAdapter,
+    source: System,
+    backup_dir: &Path,
+    interaction: &Interaction,
+) -> CustomResult<Option<SectionChange>> {
+    let Some(irk) = adapter.irk.as_ref() else {
+        return Ok(None);
+    };
+
+    let adapter_name = BtAddress::from(adapter.address.clone()).0;
+    let adapter_dir = root.join(LINUX_BT_DIR).join(&adapter_name);
+    if !adapter_dir.is_dir() {
+        debug!("adapter {} is not used in {:?}", adapter_name, root);
+        return Ok(None);
+    }
+
+    let path = adapter_dir.join(IDENTITY_FILE);
+    let current: Option<AdapterIdentity> = read_to_string(&path)
+        .ok()
+        .and_then(|identity| serde_ini::from_str(&identity).ok());
+    let updated = AdapterIdentity::new(irk);
+    if current.as_ref().is_some_and(|c| c.key() == updated.key()) {
+        debug!("{:?} already has the local IRK of {}", path, source);
+        return Ok(None);
+    }
+
+    warn!(
+        "local IRK of adapter {} differs between {} and {:?}, LE devices using privacy \
+        take them for two computers. Devices paired only in linux will have to be paired again \
+        and bluetooth.service restarted",
+        adapter_name, source, root
+    );
+    let question = format!(
+        "replace the local IRK of adapter {} in {}?",
+        adapter_name,
+        root.display()
+    );
+    if !confirm(&question, interaction)? {
+        return Ok(None);
+    }
+
+    if path.exists() {
+        let adapter_backup_dir = backup_dir.join(&adapter_name);
+        create_dir_all(&adapter_backup_dir).map_err(|e| e.into())?;
+        copy(&path, adapter_backup_dir.join(IDENTITY_FILE)).map_err(|e| e.into())?;
+    }
+
+    let identity = serde_ini::to_string(&updated).map_err(|e| e.into())?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .map_err(|e| e.into())?;
+    file.write_all(identity.as_bytes()).map_err(|e| e.into())?;
+    info!(adapter = adapter_name.as_str(), action = "identity"; "updated {:?}", path);
+
+    Ok(Some(SectionChange {
+        section: "IdentityResolvingKey".to_string(),
+        before: current.map(|c| fingerprint(c.key())),
+        after: Some(fingerprint(updated.key())),
+    }))
+}
@@ -0,0 +1,251 @@
+use std::{
+    fs::{read_to_string, OpenOptions},
+    io::{stdin, stdout, Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bt_device::{
+        linux_bt_device::{
+            bluez_address_type, bytes_to_linux_hex_key, linux_hex_key_to_bytes, BtAddress,
+        },
+        uni_bt_device::{self, AddressType, UniBtAdapter, UniBtDevice},
+    },
+    error::CustomError,
+    pipeline::{KeySink, KeySource, Policy, SourceKeys, System},
+    report, CustomResult,
+};
+
+/// Keys saved with `export`, `-` is stdin when reading and stdout when writing
+pub struct JsonFile {
+    pub path: PathBuf,
+}
+
+/// Whole file, keys are written the way bluez writes them
+///
+/// ## Example
+/// ```
+/// {"devices":[{"adapter":"C0:FB:F9:60:1C:13","address":"4C:87:5D:26:DC:9F",
+///   "address_type":"public","name":"WH-1000XM4","link_key":"786DC4332D385A48C4E718FE0B84FF20"}],
+///  "adapters":[{"address":"C0:FB:F9:60:1C:13","irk":"9E0C27A4F5E0B5C8D2C1A9D84E3F1B67"}]}
+/// ```
+#[derive(Serialize, Deserialize)]
+struct KeysFile {
+    devices: Vec<DeviceKeys>,
+    #[serde(default)]
+    adapters: Vec<AdapterKeys>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DeviceKeys {
+    adapter: String,
+    address: String,
+    /// `public`, `static` or `other`
+    address_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ltk: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    e_div: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    e_rand: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    irk: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    csrk: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    le_security: Option<LeSecurityKeys>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LeSecurityKeys {
+    mitm: bool,
+    secure_connections: bool,
+    key_size: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdapterKeys {
+    address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    irk: Option<String>,
+}
+
+impl KeySource for JsonFile {
+    fn read(&mut self) -> CustomResult<SourceKeys> {
+        let json = if self.path.as_os_str() == "-" {
+            let mut json = String::new();
+            stdin().read_to_string(&mut json).map_err(|e| e.into())?;
+            json
+        } else {
+            read_to_string(&self.path).map_err(|e| e.into())?
+        };
+        let file: KeysFile = serde_json::from_str(&json).map_err(|e| e.into())?;
+
+        Ok(SourceKeys {
+            source: self.path.display().to_string(),
+            system: System::File,
+            devices: file
+                .devices
+                .iter()
+                .map(uni_device)
+                .collect::<CustomResult<_>>()?,
+            adapters: file
+                .adapters
+                .iter()
+                .map(uni_adapter)
+                .collect::<CustomResult<_>>()?,
+        })
+    }
+}
+
+impl KeySink for JsonFile {
+    fn system(&self) -> System {
+        System::File
+    }
+
+    fn target(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Writes every device of `keys`, the file is replaced as a whole and only its
+    /// owner may read a new one
+    fn write(&mut self, keys: &SourceKeys, _policy: &Policy) -> CustomResult<report::RootReport> {
+        let file = KeysFile {
+            devices: keys.devices.iter().map(device_keys).collect(),
+            adapters: keys
+                .adapters
+                .iter()
+                .map(|a| AdapterKeys {
+                    address: BtAddress::from(a.address.clone()).0,
+                    irk: a.irk.as_ref().map(|k| bytes_to_linux_hex_key(&k.0)),
+                })
+                .collect(),
+        };
+        let mut json = serde_json::to_string_pretty(&file).map_err(|e| e.into())?;
+        json.push('\n');
+
+        if self.path.as_os_str() == "-" {
+            stdout().write_all(json.as_bytes()).map_err(|e| e.into())?;
+        } else {
+            let mut out = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&self.path)
+                .map_err(|e| e.into())?;
+            out.write_all(json.as_bytes()).map_err(|e| e.into())?;
+            info!(
+                "saved {} device(s) into {:?}",
+                file.devices.len(),
+                self.path
+            );
+        }
+
+        Ok(report::RootReport {
+            root: self.path.clone(),
+            backup_dir: None,
+            identities: vec![],
+            devices: file
+                .devices
+                .into_iter()
+                .map(|d| report::DeviceReport {
+                    adapter: d.adapter,
+                    address: d.address,
+                    name: d.name,
+                    outcome: report::Outcome::Updated,
+                    policy: None,
+                })
+                .collect(),
+        })
+    }
+}
+
+fn device_keys(device: &UniBtDevice) -> DeviceKeys {
+    DeviceKeys {
+        adapter: BtAddress::from(device.parent_address.clone()).0,
+        address: BtAddress::from(device.address.clone()).0,
+        address_type: bluez_address_type(device.address.1)
+            .unwrap_or("other")
+            .to_string(),
+        name: device.meta.as_ref().and_then(|m| m.name.clone()),
+        link_key: device
+            .link_key
+            .as_ref()
+            .map(|k| bytes_to_linux_hex_key(&k.0)),
+        ltk: device.ltk.as_ref().map(|k| bytes_to_linux_hex_key(&k.0)),
+        e_div: device.e_div.as_ref().map(|d| u32::from_le_bytes(d.0)),
+        e_rand: device.e_rand.as_ref().map(|r| u64::from_le_bytes(r.0)),
+        irk: device.irk.as_ref().map(|k| bytes_to_linux_hex_key(&k.0)),
+        csrk: device.csrk.as_ref().map(|k| bytes_to_linux_hex_key(&k.0)),
+        le_security: device.le_security.as_ref().map(|s| LeSecurityKeys {
+            mitm: s.mitm,
+            secure_connections: s.secure_connections,
+            key_size: s.key_size,
+        }),
+    }
+}
+
+fn uni_device(device: &DeviceKeys) -> CustomResult<UniBtDevice> {
+    let address_type = match device.address_type.as_str() {
+        "public" => AddressType::Public,
+        "static" => AddressType::StaticRandom,
+        _ => AddressType::Other,
+    };
+
+    Ok(UniBtDevice {
+        address: uni_address(&device.address, address_type)?,
+        parent_address: uni_address(&device.adapter, AddressType::Public)?,
+        link_key: key(&device.link_key)?.map(uni_bt_device::LinkKey),
+        ltk: key(&device.ltk)?.map(uni_bt_device::Ltk),
+        e_rand: device.e_rand.map(|r| uni_bt_device::ERand(r.to_le_bytes())),
+        e_div: device.e_div.map(|d| uni_bt_device::EDiv(d.to_le_bytes())),
+        irk: key(&device.irk)?.map(uni_bt_device::Irk),
+        csrk: key(&device.csrk)?.map(uni_bt_device::Csrk),
+        le_security: device
+            .le_security
+            .as_ref()
+            .map(|s| uni_bt_device::LeSecurity {
+                mitm: s.mitm,
+                secure_connections: s.secure_connections,
+                key_size: s.key_size,
+            }),
+        meta: device.name.clone().map(|name| uni_bt_device::DeviceMeta {
+            name: Some(name),
+            ..Default::default()
+        }),
+    })
+}
+
+fn uni_adapter(adapter: &AdapterKeys) -> CustomResult<UniBtAdapter> {
+    Ok(UniBtAdapter {
+        address: uni_address(&adapter.address, AddressType::Public)?,
+        irk: key(&adapter.irk)?.map(uni_bt_device::Irk),
+    })
+}
+
+fn uni_address(address: &str, address_type: AddressType) -> CustomResult<uni_bt_device::Address> {
+    BtAddress(address.to_string())
+        .to_uni(address_type)
+        .ok_or_else(|| -> CustomError { format!("invalid address {:?}", address).into() })
+}
+
+/// "786DC4332D385A48C4E718FE0B84FF20" -> [0x78, 0x6d, ...], an invalid key is an error
+/// rather than a missing one. Keys are never printed
+fn key(hex: &Option<String>) -> CustomResult<Option<[u8; 16]>> {
+    hex.as_deref()
+        .map(|hex| {
+            linux_hex_key_to_bytes(hex).ok_or_else(|| -> CustomError {
+                format!("invalid key of {} hex digits", hex.len()).into()
+            })
+        })
+        .transpose()
+}
@@ -0,0 +1,220 @@
+use std::{
+    fs::{create_dir_all, read_to_string, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    bt_device::{
+        linux_bt_device::{authenticated, bytes_to_linux_hex_key},
+        uni_bt_device::UniBtDevice,
+    },
+    pipeline::System,
+    utils::fingerprint,
+    CustomResult, LINUX_LEDGER_PATH,
+};
+
+/// Which way keys were copied, written as e.g. `windows-to-linux`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Direction {
+    pub from: System,
+    pub to: System,
+}
+
+/// One line of the ledger, written for every device whose info file or windows
+/// registry keys were changed
+///
+/// ## Example
+/// ```
+/// {"time":"2023-11-25T21:01:32Z","direction":"windows-to-linux","source":"/mnt/windows",
+///  "adapter":"C0:FB:F9:60:1C:13","device":"4C:87:5D:26:DC:9F","name":"WH-1000XM4",
+///  "sections":[{"section":"LinkKey","before":"9d0c43e1","after":"15ce4746"}]}
+/// ```
+#[derive(Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub time: String,
+    pub direction: Direction,
+    /// Mount point of the source partition or path of a saved export
+    pub source: String,
+    pub adapter: String,
+    /// Address of the device, the adapter's own for a change of its local IRK
+    pub device: String,
+    pub name: Option<String>,
+    pub sections: Vec<SectionChange>,
+}
+
+/// Key section that was added, replaced or removed, identified by fingerprints only
+#[derive(Serialize, Deserialize)]
+pub struct SectionChange {
+    pub section: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Key sections which differ between `before` and `after`, as returned by
+/// `linux_bt_device::BtDevice::key_sections`
+pub fn changed_sections(
+    before: &[(&'static str, String)],
+    after: &[(&'static str, String)],
+) -> Vec<SectionChange> {
+    let find = |sections: &[(&'static str, String)], name: &str| {
+        sections
+            .iter()
+            .find(|(section, _)| *section == name)
+            .map(|(_, key)| fingerprint(key))
+    };
+
+    let mut names: Vec<&'static str> = vec![];
+    for (section, _) in before.iter().chain(after) {
+        if !names.contains(section) {
+            names.push(section);
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| SectionChange {
+            section: name.to_string(),
+            before: find(before, name),
+            after: find(after, name),
+        })
+        .filter(|c| c.before != c.after)
+        .collect()
+}
+
+/// Same as `changed_sections` for keys written into another system than linux, named
+/// after the bluez sections holding them
+pub fn changed_keys(before: &UniBtDevice, after: &UniBtDevice) -> Vec<SectionChange> {
+    let sections = |d: &UniBtDevice| {
+        let mut sections = vec![];
+        if let Some(link_key) = d.link_key.as_ref() {
+            sections.push(("LinkKey", bytes_to_linux_hex_key(&link_key.0)));
+        }
+        if let Some(ltk) = d.ltk.as_ref() {
+            let security = d.le_security.as_ref().map_or(String::new(), |s| {
+                format!("{}:{}:", authenticated(s), s.key_size)
+            });
+            let e_div = d.e_div.as_ref().map_or(0, |e| u32::from_le_bytes(e.0));
+            let rand = d.e_rand.as_ref().map_or(0, |e| u64::from_le_bytes(e.0));
+            sections.push((
+                "LongTermKey",
+                format!(
+                    "{}:{}{}:{}",
+                    bytes_to_linux_hex_key(&ltk.0),
+                    security,
+                    e_div,
+                    rand
+                ),
+            ));
+        }
+        if let Some(irk) = d.irk.as_ref() {
+            sections.push(("IdentityResolvingKey", bytes_to_linux_hex_key(&irk.0)));
+        }
+        if let Some(csrk) = d.csrk.as_ref() {
+            sections.push(("LocalSignatureKey", bytes_to_linux_hex_key(&csrk.0)));
+        }
+        sections
+    };
+
+    changed_sections(&sections(before), &sections(after))
+}
+
+/// Appends entries to the ledger of the linux installation at `root`
+pub fn append(root: &Path, entries: &[LedgerEntry]) -> CustomResult<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let path = root.join(LINUX_LEDGER_PATH);
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir).map_err(|e| e.into())?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| e.into())?;
+    for entry in entries {
+        let line = serde_json::to_string(entry).map_err(|e| e.into())?;
+        writeln!(file, "{}", line).map_err(|e| e.into())?;
+    }
+    Ok(())
+}
+
+/// Entries of the ledger of the linux installation at `root`, oldest first
+pub fn read(root: &Path) -> CustomResult<Vec<LedgerEntry>> {
+    let path = root.join(LINUX_LEDGER_PATH);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    read_to_string(&path)
+        .map_err(|e| e.into())?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).map_err(|e| e.into()))
+        .collect()
+}
+
+/// Prints one line per entry, optionally only of a single device, e.g.
+/// `2023-11-25T21:01:32Z windows-to-linux /mnt/windows C0:FB:F9:60:1C:13 WH-1000XM4 (4C:87:5D:26:DC:9F) LinkKey 9d0c43e1 -> 15ce4746`
+pub fn print_history(root: &Path, device: Option<&str>) -> CustomResult<()> {
+    let entries = read(root)?;
+
+    for entry in entries
+        .iter()
+        .filter(|e| device.is_none_or(|d| e.device.eq_ignore_ascii_case(d)))
+    {
+        let device = match entry.name.as_ref() {
+            Some(name) => format!("{} ({})", name, entry.device),
+            None => entry.device.clone(),
+        };
+        let mut line = format!(
+            "{} {} {} {} {}",
+            entry.time, entry.direction, entry.source, entry.adapter, device
+        );
+
+        for change in entry.sections.iter() {
+            line.push_str(&format!(
+                " {} {} -> {}",
+                change.section,
+                change.before.as_deref().unwrap_or("none"),
+                change.after.as_deref().unwrap_or("none")
+            ));
+        }
+
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-to-{}", self.from, self.to)
+    }
+}
+
+impl Serialize for Direction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    /// "windows-to-linux" -> Direction { from: Windows, to: Linux }
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let direction = String::deserialize(deserializer)?;
+        let (from, to) = direction.split_once("-to-").ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid direction {:?}", direction))
+        })?;
+
+        Ok(Direction {
+            from: from.parse().map_err(serde::de::Error::custom)?,
+            to: to.parse().map_err(serde::de::Error::custom)?,
+        })
+    }
+}
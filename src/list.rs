@@ -0,0 +1,35 @@
+use crate::{bt_device::uni_bt_device::UniBtDevice, names::NameIndex, utils::format_time};
+
+/// Prints one line per device, e.g.
+/// `C0:FB:F9:60:1C:13 4C:87:5D:26:DC:9F "WH-1000XM4" class 0x240404 id 054c:0d58 last connected 2023-11-25T21:01:32Z`
+/// Devices without a name in windows are named after linux when bluez has seen them
+pub fn print_devices(devices: &[UniBtDevice], names: &NameIndex) {
+    for device in devices {
+        let mut line = format!("{} {}", device.parent_address, device.address);
+
+        if let Some(name) = names.device_name(device) {
+            line.push_str(&format!(" {:?}", name));
+        }
+
+        if let Some(meta) = device.meta.as_ref() {
+            if let Some(class) = meta.class {
+                line.push_str(&format!(" class 0x{:06x}", class));
+            }
+
+            if let Some(device_id) = meta.device_id.as_ref() {
+                line.push_str(&format!(
+                    " id {:04x}:{:04x}",
+                    device_id.vendor, device_id.product
+                ));
+            }
+
+            if let Some(last_connected) = meta.last_connected {
+                line.push_str(&format!(" last connected {}", format_time(last_connected)));
+            } else if let Some(last_seen) = meta.last_seen {
+                line.push_str(&format!(" last seen {}", format_time(last_seen)));
+            }
+        }
+
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::net::UnixDatagram,
+    path::Path,
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    warn, Level, LevelFilter, Log, Metadata, Record,
+};
+
+use crate::utils::format_time;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "bt-dualboot";
+
+/// Writes records to stderr and, when asked, to a file and the journal. Key-value
+/// pairs of a record become fields, e.g. `info!(device = "4C:87:5D:26:DC:9F",
+/// action = "updated"; "...")` is logged with `DEVICE=` and `ACTION=`
+struct Logger {
+    stderr_level: LevelFilter,
+    /// Level of the file and the journal, which are read after the fact
+    persistent_level: LevelFilter,
+    file: Option<Mutex<File>>,
+    journal: Option<UnixDatagram>,
+}
+
+/// Installs the logger. `verbosity` is the number of `-v`, stderr gets warnings
+/// without any and only errors with `quiet`. The file and the journal get at least
+/// info, so unattended runs can be diagnosed afterwards
+pub fn init(verbosity: u8, quiet: bool, log_file: Option<&Path>, journal: bool) {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let stderr_level = if quiet { LevelFilter::Error } else { level };
+    let persistent_level = level.max(LevelFilter::Info);
+
+    let mut failures = vec![];
+    let file =
+        log_file.and_then(
+            |path| match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => Some(Mutex::new(file)),
+                Err(e) => {
+                    failures.push(format!("can't open log file {:?}: {:?}", path, e));
+                    None
+                }
+            },
+        );
+    let journal = journal
+        .then(|| match connect_journal() {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                failures.push(format!("can't connect to journald: {:?}", e));
+                None
+            }
+        })
+        .flatten();
+
+    let max_level = if file.is_some() || journal.is_some() {
+        stderr_level.max(persistent_level)
+    } else {
+        stderr_level
+    };
+    let logger = Logger {
+        stderr_level,
+        persistent_level,
+        file,
+        journal,
+    };
+    log::set_boxed_logger(Box::new(logger)).expect("init logger");
+    log::set_max_level(max_level);
+
+    for failure in failures {
+        warn!("{}", failure);
+    }
+}
+
+fn connect_journal() -> std::io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(JOURNAL_SOCKET)?;
+    Ok(socket)
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        let mut fields = Fields::default();
+        let _ = record.key_values().visit(&mut fields);
+        let line = format!(
+            "{} {:<5} [{}] {}",
+            format_time(SystemTime::now()),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        if record.level() <= self.stderr_level {
+            eprintln!("{}", line);
+        }
+
+        if record.level() > self.persistent_level {
+            return;
+        }
+
+        if let Some(file) = self.file.as_ref() {
+            let mut line = line.clone();
+            for (key, value) in fields.0.iter() {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+
+        if let Some(journal) = self.journal.as_ref() {
+            let _ = journal.send(&journal_entry(record, &fields));
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.as_ref() {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Key-value pairs of a record as journal field names, e.g. `("DEVICE", "4C:87:...")`
+#[derive(Default)]
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let key: String = key
+            .as_str()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' => c.to_ascii_uppercase(),
+                'A'..='Z' | '0'..='9' => c,
+                _ => '_',
+            })
+            .collect();
+        self.0
+            .push((key.trim_start_matches('_').to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Datagram of the native journal protocol, values with a newline are written with
+/// their length instead of `=`
+fn journal_entry(record: &Record, fields: &Fields) -> Vec<u8> {
+    let priority = match record.level() {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    };
+
+    let mut entry = vec![];
+    let mut push = |key: &str, value: &str| {
+        if value.contains('\n') {
+            entry.extend_from_slice(key.as_bytes());
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            entry.extend_from_slice(value.as_bytes());
+            entry.push(b'\n');
+        } else {
+            entry.extend_from_slice(format!("{}={}\n", key, value).as_bytes());
+        }
+    };
+
+    push("MESSAGE", &record.args().to_string());
+    push("PRIORITY", &priority.to_string());
+    push("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+    push("TARGET", record.target());
+    for (key, value) in fields.0.iter() {
+        push(key, value);
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_journal_fields() {
+        let fields = Fields(vec![
+            ("DEVICE".to_string(), "4C:87:5D:26:DC:9F".to_string()),
+            (
+                "ERROR".to_string(),
+                "no such file\nor directory".to_string(),
+            ),
+        ]);
+        let entry = journal_entry(
+            &Record::builder()
+                .args(format_args!("updated device"))
+                .level(Level::Warn)
+                .target("bt_dualboot_rs::bluez")
+                .build(),
+            &fields,
+        );
+
+        let mut expected = b"MESSAGE=updated device\n\
+            PRIORITY=4\n\
+            SYSLOG_IDENTIFIER=bt-dualboot\n\
+            TARGET=bt_dualboot_rs::bluez\n\
+            DEVICE=4C:87:5D:26:DC:9F\n\
+            ERROR\n"
+            .to_vec();
+        expected.extend_from_slice(&25u64.to_le_bytes());
+        expected.extend_from_slice(b"no such file\nor directory\n");
+        assert_eq!(entry, expected);
+    }
+}
@@ -0,0 +1,62 @@
+use std::{fs::read_to_string, path::Path};
+
+use log::debug;
+
+use crate::{
+    bt_device::{mac_bt_device, uni_bt_device},
+    choose_mount,
+    pipeline::{KeySource, SourceKeys, System},
+    CustomResult, Interaction, MACOS_BT_PLIST_PATH,
+};
+
+/// com.apple.Bluetooth.plist of a mounted macos volume
+pub struct MacosPlist<'a> {
+    pub interaction: &'a Interaction,
+}
+
+impl KeySource for MacosPlist<'_> {
+    fn read(&mut self) -> CustomResult<SourceKeys> {
+        get_plist_bt_devices(self.interaction)
+    }
+}
+
+/// Devices paired in macos and the mount point of the macos volume
+pub fn get_plist_bt_devices(interaction: &Interaction) -> CustomResult<SourceKeys> {
+    let mac_mount = choose_mount(get_macos_mounts()?, "macos", interaction)?;
+
+    let plist_path = Path::new(&mac_mount).join(MACOS_BT_PLIST_PATH);
+    debug!("reading {:?}", plist_path);
+    let plist = plist::Value::from_file(&plist_path).map_err(|e| e.into())?;
+    let (link_keys, smp_keys) = mac_bt_device::pairing_dictionaries(&plist);
+
+    let mut all_devices = vec![];
+    if let Some(link_keys) = link_keys {
+        all_devices.extend(mac_bt_device::link_key_devices(link_keys));
+    }
+    if let Some(smp_keys) = smp_keys {
+        all_devices.extend(mac_bt_device::le_devices(smp_keys));
+    }
+    let all_devices = uni_bt_device::merge_dual_mode(all_devices);
+    debug!("found {} macos device(s)", all_devices.len());
+
+    Ok(SourceKeys {
+        source: mac_mount,
+        system: System::Macos,
+        devices: all_devices,
+        adapters: vec![],
+    })
+}
+
+fn get_macos_mounts() -> CustomResult<Vec<String>> {
+    let mounts = read_to_string("/proc/mounts").map_err(|e| e.into())?;
+
+    let mac_mounts: Vec<_> = mounts
+        .split('\n')
+        .filter(|l| l.starts_with("/dev/"))
+        .filter_map(|l| l.split(' ').nth(1))
+        .filter(|mnt_p| Path::new(mnt_p).join(MACOS_BT_PLIST_PATH).exists())
+        .map(|mnt_p| mnt_p.to_string())
+        .collect();
+
+    Ok(mac_mounts)
+}
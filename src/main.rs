@@ -1,267 +1,359 @@
-use bt_device::{linux_bt_device, uni_bt_device::UniBtDevice};
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Commands, Output, Source, SyncArgs, Target};
 use error::CustomError;
-use inquire::Select;
-use log::{debug, info, warn};
+use hooks::Hooks;
+use inquire::{Confirm, Select};
+use json_file::JsonFile;
+use log::{debug, error, info, warn};
+use pipeline::{KeySink, Policy};
+use privilege::Access;
 use std::{
-    collections::HashMap,
-    fs::{read_to_string, File},
-    io::Write,
-    path::Path,
-    process::{Command, Stdio},
+    fs::read_to_string,
+    io::{stdin, IsTerminal},
+    path::{Path, PathBuf},
 };
 
-use crate::{bt_device::win_bt_device, utils::is_valid_64_hex};
+use crate::names::NameIndex;
 
+mod bluez;
 mod bt_device;
 mod cli;
+mod config;
 mod error;
+mod freshness;
+mod hive;
+mod hooks;
+mod identity;
+mod json_file;
+mod ledger;
+mod list;
+mod logging;
+mod macos;
+mod names;
+mod ntfs;
+mod pipeline;
+mod privilege;
+mod report;
+mod service;
+mod status;
+mod tui;
 mod utils;
+mod verify;
+mod windows;
 
 const WINDOWS10_REGISTRY_PATH: &str = "Windows/System32/config/SYSTEM";
+const MACOS_BT_PLIST_PATH: &str = "Library/Preferences/com.apple.Bluetooth.plist";
 const REG_KEY_BLUETOOTH_PAIRING_KEYS: &str = r"ControlSet001\Services\BTHPORT\Parameters\Keys";
-const LINUX_BT_DIR: &str = "/var/lib/bluetooth";
+const REG_KEY_BLUETOOTH_DEVICES: &str = r"ControlSet001\Services\BTHPORT\Parameters\Devices";
+/// Relative to the root of a linux installation
+const LINUX_BT_DIR: &str = "var/lib/bluetooth";
+const LINUX_BACKUP_DIR: &str = "var/lib/bt-dualboot/backups";
+const LINUX_OS_RELEASE: &str = "etc/os-release";
+const LINUX_LEDGER_PATH: &str = "var/lib/bt-dualboot/ledger.jsonl";
+const PARTITION_LINK_DIRS: [&str; 3] = [
+    "/dev/disk/by-uuid",
+    "/dev/disk/by-label",
+    "/dev/disk/by-partuuid",
+];
 
 pub type CustomResult<T> = Result<T, CustomError>;
 
 fn main() {
     let cli = Cli::parse();
 
-    if cli.verbose {
-        simple_logger::init_with_level(log::Level::Debug).expect("init logger");
-    } else {
-        simple_logger::init_with_level(log::Level::Warn).expect("init logger");
+    logging::init(cli.verbose, cli.quiet, cli.log_file.as_deref(), cli.journal);
+
+    let config = or_exit(config::load(cli.config.as_deref()));
+    let interactive = !cli.non_interactive && stdin().is_terminal();
+    if !cli.non_interactive && !interactive {
+        info!("stdin is not a terminal, prompts are disabled");
     }
+    let interaction = Interaction {
+        partition: cli.partition.clone().or(config.partition()),
+        interactive,
+        assume_yes: cli.yes,
+    };
 
-    let bt_devices = get_reged_bt_devices().unwrap();
-    update_linux_devices(bt_devices);
+    let command = cli.command.unwrap_or(Commands::Sync(SyncArgs::default()));
+    or_exit(privilege::ensure(
+        &needed_access(&command, cli.reg_export.as_deref()),
+        cli.elevate,
+        &interaction,
+    ));
+
+    match command {
+        Commands::Sync(args) => {
+            let root = args
+                .root
+                .first()
+                .cloned()
+                .unwrap_or_else(|| PathBuf::from("/"));
+            let mut source = pipeline::key_source(
+                args.source,
+                &root,
+                args.input.clone(),
+                cli.reg_export.clone(),
+                &interaction,
+            );
+            let mut sinks = or_exit(pipeline::key_sinks(
+                args.source,
+                args.target(),
+                &args.root,
+                cli.reg_export.clone(),
+                &interaction,
+            ));
+            let (conflict, device_conflicts) = or_exit(config.conflict_policies());
+            let policy = Policy {
+                force: args.force,
+                fill_metadata: args.fill_metadata,
+                conflict: args.conflict.or(conflict).unwrap_or_default(),
+                device_conflicts,
+            };
+
+            let report = or_exit(pipeline::sync(
+                &mut *source,
+                &mut sinks,
+                &policy,
+                &config.hooks(),
+            ));
+            match args.output {
+                Output::Table => report.print_table(),
+                Output::Json => or_exit(report.print_json()),
+            }
+            std::process::exit(report.exit_code());
+        }
+        Commands::Export(args) => {
+            let root = args.root.unwrap_or_else(|| PathBuf::from("/"));
+            let mut source = pipeline::key_source(
+                args.source,
+                &root,
+                None,
+                cli.reg_export.clone(),
+                &interaction,
+            );
+            let mut sinks: Vec<Box<dyn KeySink>> = vec![Box::new(JsonFile { path: args.file })];
+            let report = or_exit(pipeline::sync(
+                &mut *source,
+                &mut sinks,
+                &Policy::default(),
+                &Hooks::default(),
+            ));
+            if !report.errors.is_empty() {
+                std::process::exit(report::EXIT_FATAL);
+            }
+        }
+        Commands::Status => {
+            std::process::exit(status::run(cli.reg_export.as_deref(), &interaction))
+        }
+        Commands::Verify(args) => {
+            let root = args.root.unwrap_or_else(|| PathBuf::from("/"));
+            std::process::exit(verify::run(&root, cli.reg_export.as_deref(), &interaction))
+        }
+        Commands::Tui(args) => {
+            let root = args.root.unwrap_or_else(|| PathBuf::from("/"));
+            std::process::exit(or_exit(tui::run(
+                &root,
+                cli.reg_export.as_deref(),
+                &interaction,
+                &config.hooks(),
+            )))
+        }
+        Commands::List => {
+            let keys = or_exit(windows::get_reged_bt_devices(
+                cli.reg_export.as_deref(),
+                &interaction,
+            ));
+            list::print_devices(&keys.devices, &NameIndex::load(Path::new("/")));
+        }
+        Commands::History(args) => {
+            let root = args.root.unwrap_or_else(|| PathBuf::from("/"));
+            or_exit(ledger::print_history(&root, args.device.as_deref()));
+        }
+        Commands::InstallService => {
+            or_exit(service::install(cli.config.as_deref(), &config));
+        }
+        Commands::UninstallService => {
+            or_exit(service::uninstall());
+        }
+    }
 }
 
-fn get_reged_bt_devices() -> CustomResult<Vec<UniBtDevice>> {
-    let win_mounts = get_windows_mounts()?;
-    let win_mounts_str: Vec<_> = win_mounts.iter().map(|x| x.as_str()).collect();
+/// Paths `command` reads or writes which are known before it starts, the windows
+/// partition is checked once it's chosen
+fn needed_access(command: &Commands, reg_export: Option<&Path>) -> Vec<(PathBuf, Access)> {
+    let bt_dir = |root: Option<&PathBuf>| {
+        root.map_or(Path::new("/"), |r| r.as_path())
+            .join(LINUX_BT_DIR)
+    };
 
-    if win_mounts.is_empty() {
-        return Err("no windows partitions".into());
+    let mut needs = vec![];
+    let reads_windows = match command {
+        Commands::Sync(args) => {
+            match (args.source, args.input.as_ref()) {
+                (Source::Linux, _) => needs.push((bt_dir(args.root.first()), Access::Read)),
+                (Source::Json, Some(input)) => needs.push((input.clone(), Access::Read)),
+                _ => (),
+            }
+            if args.target() == Target::Linux {
+                if args.root.is_empty() {
+                    needs.push((bt_dir(None), Access::Write));
+                }
+                for root in args.root.iter() {
+                    needs.push((bt_dir(Some(root)), Access::Write));
+                }
+            }
+            matches!(args.source, Source::Windows)
+        }
+        Commands::Export(args) => {
+            if matches!(args.source, Source::Linux) {
+                needs.push((bt_dir(args.root.as_ref()), Access::Read));
+            }
+            matches!(args.source, Source::Windows)
+        }
+        Commands::Status => {
+            needs.push((bt_dir(None), Access::Read));
+            true
+        }
+        Commands::Verify(args) => {
+            needs.push((bt_dir(args.root.as_ref()), Access::Read));
+            true
+        }
+        Commands::Tui(args) => {
+            needs.push((bt_dir(args.root.as_ref()), Access::Read));
+            true
+        }
+        Commands::List => true,
+        Commands::History(args) => {
+            let root = args.root.as_deref().unwrap_or(Path::new("/"));
+            needs.push((root.join(LINUX_LEDGER_PATH), Access::Read));
+            false
+        }
+        Commands::InstallService | Commands::UninstallService => {
+            needs.push((PathBuf::from(service::SERVICE_DIR), Access::Write));
+            false
+        }
+    };
+    if let Some(reg_export) = reg_export.filter(|_| reads_windows) {
+        needs.push((reg_export.to_path_buf(), Access::Read));
     }
-    debug!("found {} windows partition(s)", win_mounts.len());
 
-    let win_mount = if win_mounts.len() == 1 {
-        win_mounts.get(0).expect("checked len by 1")
-    } else {
-        Select::new(
-            "multiple windows partitions detected. which one to use?",
-            win_mounts_str,
-        )
-        .prompt()
-        .map_err(|e| e.into())?
-    };
+    needs
+}
 
-    let output = get_chntpw_export(win_mount)?;
-    let output_clean = output
-        .split("\r\n")
-        .skip(1)
-        .take_while(|l| !l.starts_with("reged version"))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let raw_values: HashMap<String, HashMap<String, String>> =
-        serde_ini::from_str(&output_clean).map_err(|e| e.into())?;
+/// Logs the error and exits, for failures which leave nothing else to do
+fn or_exit<T>(result: CustomResult<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let bt_values_1: Vec<_> = raw_values
-        .iter()
-        .filter(|(k, _)| {
-            // Match bt adapters
-            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys\\c0fbf9601c13
-            let path_len = k.split("\\").count();
-            path_len == 8
-        })
-        .map(|(p_k, h)| {
-            // Remove "" quotes around the keys
-            // "AuthReq" -> AuthReq
-            let n_h: HashMap<_, _> = h
-                .into_iter()
-                .map(|(k, v)| (k.trim_matches('"').to_string(), v))
-                .collect();
-            (p_k, n_h)
-        })
-        .flat_map(|(p_k, h)| {
-            let parent_address = p_k
-                .split('\\')
-                .collect::<Vec<&str>>()
-                .into_iter()
-                .rev()
-                .next()
-                .expect("checked by path_len")
-                .to_string();
+/// What to do instead of asking the user
+pub struct Interaction {
+    /// Mount point, UUID or label given on the command line or in the config
+    pub partition: Option<String>,
+    /// Whether the user may be asked, prompts block forever without a terminal
+    pub interactive: bool,
+    /// Answer yes to every confirmation
+    pub assume_yes: bool,
+}
 
-            h.into_iter()
-                .filter(|(k, _)| is_valid_64_hex(k))
-                .map(|(k, v)| {
-                    win_bt_device::BtDeviceBuilder::new()
-                        .address(k)
-                        .ltk(v.clone())
-                        .parent_address(parent_address.clone())
-                        .build()
-                })
-                .collect::<Vec<_>>()
-        })
-        .collect();
-    debug!("found {} device(s)", bt_values_1.len());
+/// Asks a yes/no question, without prompts only `--yes` makes it a yes
+fn confirm(question: &str, interaction: &Interaction) -> CustomResult<bool> {
+    confirm_list(question, &[], interaction)
+}
 
-    let bt_values_2: Vec<_> = raw_values
-        .into_iter()
-        .filter(|(k, _)| {
-            // Ignore an empty set and bt adapters
-            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys
-            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys\\c0fbf9601c13
-            let path_len = k.split("\\").count();
-            path_len > 8
-        })
-        .map(|(k, v)| {
-            // Remove "" quotes around the keys
-            // "AuthReq" -> AuthReq
-            let n_h: HashMap<_, _> = v
-                .into_iter()
-                .map(|(n_k, n_v)| (n_k.trim_matches('"').to_string(), n_v))
-                .collect();
-            (k, n_h)
-        })
-        .map(|(k, v)| {
-            let parent_address = k
-                .split("\\")
-                .collect::<Vec<&str>>()
-                .into_iter()
-                .rev()
-                .skip(1)
-                .next()
-                .expect("should have adapter's mac")
-                .to_string();
+/// `confirm` with the things it's about, e.g. device labels, listed below the question
+fn confirm_list(question: &str, items: &[String], interaction: &Interaction) -> CustomResult<bool> {
+    if interaction.assume_yes {
+        return Ok(true);
+    }
 
-            win_bt_device::BtDeviceBuilder::new()
-                .entries51(v)
-                .parent_address(parent_address)
-                .build()
-        })
-        .collect();
-    debug!("found {} separate device(s)", bt_values_2.len());
+    if !interaction.interactive {
+        warn!(
+            "{}{} answering no, pass --yes to agree without prompts",
+            question,
+            items.iter().map(|i| format!(" {}", i)).collect::<String>()
+        );
+        return Ok(false);
+    }
 
-    let mut all_devices = vec![];
-    all_devices.extend(bt_values_1);
-    all_devices.extend(bt_values_2);
-    Ok(all_devices)
+    let list = items.join("\n");
+    let mut prompt = Confirm::new(question).with_default(true);
+    if !items.is_empty() {
+        prompt = prompt.with_help_message(&list);
+    }
+    prompt.prompt().map_err(|e| e.into())
 }
 
-fn get_chntpw_export(win_mount: &str) -> CustomResult<String> {
-    let win_reg = Path::new(win_mount).join(Path::new(WINDOWS10_REGISTRY_PATH));
-    if !win_reg.exists() {
-        return Err(format!("didn't find registry in windows {} partition", win_mount).into());
+/// Mount point of a partition given by its mount point, UUID or label
+fn resolve_partition(partition: &str) -> CustomResult<String> {
+    if Path::new(partition).is_dir() {
+        return Ok(partition.to_string());
     }
 
-    debug!(
-        "running chntpw on the {} registry",
-        win_reg.to_str().expect("checked existence")
-    );
-
-    let chntpw = Command::new("reged")
-        .args([
-            "-E",
-            "-x",
-            win_reg.to_str().expect("checked existence"),
-            r"HKEY_LOCAL_MACHINE\SYSTEM",
-            REG_KEY_BLUETOOTH_PAIRING_KEYS,
-            "/dev/stdout",
-        ])
-        .stdout(Stdio::piped())
-        .spawn()
-        .map_err(|e| e.into())?;
-
-    let output = chntpw.wait_with_output().map_err(|e| e.into())?.stdout;
-    let output_str = String::from_utf8(output).map_err(|e| e.into())?;
-    Ok(output_str)
-}
+    let device = PARTITION_LINK_DIRS
+        .iter()
+        .map(|dir| Path::new(dir).join(partition))
+        .find_map(|link| link.canonicalize().ok())
+        .ok_or_else(|| -> CustomError {
+            format!("no partition with mount point, UUID or label {}", partition).into()
+        })?;
 
-fn get_windows_mounts() -> CustomResult<Vec<String>> {
     let mounts = read_to_string("/proc/mounts").map_err(|e| e.into())?;
-
-    let win_mounts: Vec<_> = mounts
+    mounts
         .split('\n')
-        .filter(|l| l.starts_with("/dev/") && !l.starts_with("/dev/loop"))
-        .map(|l| {
-            let mnt_point = l
-                .split(' ')
-                .skip(1)
-                .next()
-                .expect(&format!("no mounting point in the dev entry {}", l))
-                .to_string();
-            mnt_point
-        })
-        .filter(|mnt_p| {
-            Path::new(mnt_p)
-                .join(Path::new(WINDOWS10_REGISTRY_PATH))
-                .exists()
+        .filter_map(|l| {
+            let mut fields = l.split(' ');
+            Some((fields.next()?, fields.next()?))
         })
-        .collect();
-
-    Ok(win_mounts)
+        .find(|(dev, _)| Path::new(dev).canonicalize().ok().as_ref() == Some(&device))
+        .map(|(_, mnt_p)| mnt_p.to_string())
+        .ok_or_else(|| format!("partition {} ({:?}) is not mounted", partition, device).into())
 }
 
-fn update_linux_devices(win_devices: Vec<UniBtDevice>) {
-    win_devices
-        .into_iter()
-        .map(|d| {
-            let d_path = Path::new(LINUX_BT_DIR)
-                .join(linux_bt_device::BtAddress::from(d.parent_address.clone()).0)
-                .join(linux_bt_device::BtAddress::from(d.address.clone()).0);
-            (d, d_path)
-        })
-        .filter(|(d, d_path)| {
-            if !d_path.exists() {
-                warn!(
-                    "device from windows with mac {} is not connected in linux",
-                    linux_bt_device::BtAddress::from(d.address.clone()).0
-                );
-                false
-            } else {
-                true
-            }
-        })
-        .map(|(uni_dev, d_path)| {
-            let info_path = Path::new(&d_path).join("info");
-            let info_str = read_to_string(&info_path).expect("no info file in mac folder");
-
-            let linux_dev: linux_bt_device::BtDevice =
-                serde_ini::from_str(&info_str).expect("info always for bt device");
-
-            let mut builder = linux_bt_device::BtDeviceBuilder::new()
-                .device(linux_dev)
-                .ltk(uni_dev.ltk);
-
-            if let Some(irk) = uni_dev.irk {
-                builder = builder.irk(irk);
-            }
-
-            if let Some(csrk) = uni_dev.csrk {
-                builder = builder.csrk(csrk);
-            }
+/// Picks one of `mounts` of the `os` ("windows", "macos") partitions
+fn choose_mount(mounts: Vec<String>, os: &str, interaction: &Interaction) -> CustomResult<String> {
+    if let Some(partition) = interaction.partition.as_ref() {
+        let mount = resolve_partition(partition)?;
+        return if mounts.contains(&mount) {
+            Ok(mount)
+        } else {
+            Err(format!(
+                "partition {} is not among {} partitions: {}",
+                partition,
+                os,
+                mounts.join(", ")
+            )
+            .into())
+        };
+    }
 
-            if let Some(e_div) = uni_dev.e_div {
-                builder = builder.e_div(e_div);
-            }
+    if mounts.is_empty() {
+        return Err(format!("no {} partitions", os).into());
+    }
+    debug!("found {} {} partition(s)", mounts.len(), os);
 
-            if let Some(e_rand) = uni_dev.e_rand {
-                builder = builder.e_rand(e_rand);
-            }
+    if mounts.len() == 1 {
+        return Ok(mounts.into_iter().next().expect("checked len by 1"));
+    }
 
-            let updated_linux_dev = builder.build();
+    if !interaction.interactive {
+        return Err(format!(
+            "multiple {} partitions detected and prompts are disabled, pick one with --partition or Partition in the config: {}",
+            os,
+            mounts.join(", ")
+        )
+        .into());
+    }
 
-            (updated_linux_dev, d_path)
-        })
-        .for_each(|(d, d_path)| {
-            let str = serde_ini::to_string(&d).unwrap();
-            let mut file = File::create(d_path.join("info")).expect("can't open info file");
-            file.write_all(str.as_bytes())
-                .expect("writing of update failed");
-            info!("updated {:?} device", d_path);
-        });
+    Select::new(
+        &format!("multiple {} partitions detected. which one to use?", os),
+        mounts,
+    )
+    .prompt()
+    .map_err(|e| e.into())
 }
@@ -0,0 +1,162 @@
+use std::{
+    collections::BTreeMap,
+    fs::{read_dir, read_to_string},
+    path::Path,
+};
+
+use log::debug;
+
+use crate::{bt_device::uni_bt_device::UniBtDevice, utils::is_valid_linux_address, LINUX_BT_DIR};
+
+/// Names of devices known to bluez in a linux installation, both paired ones and
+/// the ones which were only seen, keyed by adapter and device address
+#[derive(Default)]
+pub struct NameIndex {
+    names: BTreeMap<(String, String), String>,
+}
+
+impl NameIndex {
+    /// Reads `Name` from `[General]` of `<adapter>/cache/<device>` and of
+    /// `<adapter>/<device>/info`, the latter wins
+    pub fn load(root: &Path) -> NameIndex {
+        let mut index = NameIndex::default();
+
+        let adapters = match read_dir(root.join(LINUX_BT_DIR)) {
+            Ok(adapters) => adapters,
+            Err(e) => {
+                debug!("no device names in {:?}: {:?}", root, e);
+                return index;
+            }
+        };
+
+        for adapter in adapters.filter_map(|a| a.ok()) {
+            let adapter_name = adapter.file_name().to_string_lossy().to_string();
+            if !is_valid_linux_address(&adapter_name) {
+                continue;
+            }
+
+            let cache_files = read_dir(adapter.path().join("cache")).into_iter().flatten();
+            let info_files = read_dir(adapter.path())
+                .into_iter()
+                .flatten()
+                .filter_map(|d| d.ok())
+                .map(|d| (d.file_name(), d.path().join("info")));
+
+            for (device, path) in cache_files
+                .filter_map(|d| d.ok())
+                .map(|d| (d.file_name(), d.path()))
+                .chain(info_files)
+            {
+                let device = device.to_string_lossy().to_string();
+                if !is_valid_linux_address(&device) {
+                    continue;
+                }
+
+                if let Some(name) = read_to_string(&path).ok().as_deref().and_then(general_name) {
+                    index.names.insert((adapter_name.clone(), device), name);
+                }
+            }
+        }
+
+        debug!("found {} device name(s) in {:?}", index.names.len(), root);
+        index
+    }
+
+    /// Name bluez keeps for the device, looked up on other adapters too, in address
+    /// order so the same adapter wins every run
+    pub fn name(&self, adapter: &str, address: &str) -> Option<&str> {
+        self.names
+            .get(&(adapter.to_string(), address.to_string()))
+            .or_else(|| {
+                self.names
+                    .iter()
+                    .find(|((_, a), _)| a == address)
+                    .map(|(_, name)| name)
+            })
+            .map(|name| name.as_str())
+    }
+
+    /// Name from the source system, or the one bluez knows
+    pub fn device_name(&self, uni_dev: &UniBtDevice) -> Option<String> {
+        uni_dev
+            .meta
+            .as_ref()
+            .and_then(|m| m.name.clone())
+            .or_else(|| {
+                self.name(
+                    &uni_dev.parent_address.to_string(),
+                    &uni_dev.address.to_string(),
+                )
+                .map(|name| name.to_string())
+            })
+    }
+
+    /// "WH-1000XM4 (4C:87:5D:26:DC:9F)" or just "4C:87:5D:26:DC:9F" for unknown devices
+    pub fn label(&self, uni_dev: &UniBtDevice) -> String {
+        match self.device_name(uni_dev) {
+            Some(name) => format!("{} ({})", name, uni_dev.address),
+            None => uni_dev.address.to_string(),
+        }
+    }
+
+    /// Same as `label` for devices known only by address, e.g. ones paired only in linux
+    pub fn address_label(&self, adapter: &str, address: &str) -> String {
+        match self.name(adapter, address) {
+            Some(name) => format!("{} ({})", name, address),
+            None => address.to_string(),
+        }
+    }
+}
+
+/// `Name` in the `[General]` section of a bluez `info` or `cache` file
+///
+/// ## Example
+/// ```
+/// [General]
+/// Name=WH-1000XM4
+/// ```
+fn general_name(content: &str) -> Option<String> {
+    let mut in_general = false;
+
+    for line in content.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            in_general = line == "[General]";
+        } else if in_general {
+            if let Some(name) = line.strip_prefix("Name=") {
+                return Some(name.to_string()).filter(|n| !n.is_empty());
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_first_adapter() {
+        let mut index = NameIndex::default();
+        for (adapter, name) in [
+            ("F0:00:00:00:00:01", "Kitchen speaker"),
+            ("C0:FB:F9:60:1C:13", "WH-1000XM4"),
+            ("D0:00:00:00:00:01", "Old name"),
+        ] {
+            index.names.insert(
+                (adapter.to_string(), "4C:87:5D:26:DC:9F".to_string()),
+                name.to_string(),
+            );
+        }
+
+        assert_eq!(
+            index.name("D0:00:00:00:00:01", "4C:87:5D:26:DC:9F"),
+            Some("Old name")
+        );
+        assert_eq!(
+            index.name("A0:00:00:00:00:01", "4C:87:5D:26:DC:9F"),
+            Some("WH-1000XM4")
+        );
+        assert_eq!(index.name("A0:00:00:00:00:01", "11:22:33:44:55:66"), None);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    fs::{read_dir, read_to_string, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::CustomResult;
+
+/// OEM id of a boot sector, bytes 3..11
+const OEM_ID_OFFSET: usize = 3;
+const BITLOCKER_OEM_ID: &[u8; 8] = b"-FVE-FS-";
+const NTFS_OEM_ID: &[u8; 8] = b"NTFS    ";
+
+const HIBERFIL_PATH: &str = "hiberfil.sys";
+
+/// $Volume is the fourth MFT record, flags of the volume are in its $VOLUME_INFORMATION
+const VOLUME_MFT_RECORD: u64 = 3;
+const VOLUME_INFORMATION: u32 = 0x70;
+const ATTRIBUTES_END: u32 = 0xffff_ffff;
+const VOLUME_IS_DIRTY: u16 = 0x0001;
+/// Update sequence numbers protect every 512 bytes of a record, whatever the sector size
+const FIXUP_STRIDE: usize = 512;
+
+/// Whether the partition starts with a BitLocker boot sector
+///
+/// ## Example
+/// ```
+/// eb 58 90 2d 46 56 45 2d 46 53 2d 00 02 08 00 00  |.X.-FVE-FS-.....|
+/// ```
+pub fn is_bitlocker(device: &Path) -> bool {
+    let mut boot_sector = [0u8; OEM_ID_OFFSET + 8];
+    match File::open(device).and_then(|mut f| f.read_exact(&mut boot_sector)) {
+        Ok(()) => &boot_sector[OEM_ID_OFFSET..] == BITLOCKER_OEM_ID,
+        Err(e) => {
+            debug!("can't read boot sector of {:?}: {:?}", device, e);
+            false
+        }
+    }
+}
+
+/// Partitions encrypted with BitLocker and not unlocked with cryptsetup, e.g.
+/// `["/dev/nvme0n1p3"]`
+pub fn bitlocker_partitions() -> Vec<PathBuf> {
+    // major minor  #blocks  name
+    //  259        3  409600000 nvme0n1p3
+    let partitions = read_to_string("/proc/partitions").unwrap_or_default();
+
+    partitions
+        .lines()
+        .skip(2)
+        .filter_map(|l| l.split_whitespace().nth(3))
+        .filter(|name| !is_unlocked(name))
+        .map(|name| Path::new("/dev").join(name))
+        .filter(|device| is_bitlocker(device))
+        .collect()
+}
+
+/// Whether a device mapper target sits on the partition, e.g. `/dev/mapper/windows`
+/// of `cryptsetup bitlkOpen`
+fn is_unlocked(name: &str) -> bool {
+    read_dir(Path::new("/sys/class/block").join(name).join("holders"))
+        .is_ok_and(|mut holders| holders.next().is_some())
+}
+
+/// Explains how to unlock BitLocker partitions so their registry can be read
+pub fn bitlocker_hint(partitions: &[PathBuf]) -> String {
+    let devices: Vec<_> = partitions.iter().map(|p| p.display().to_string()).collect();
+    let device = devices.first().map(|d| d.as_str()).unwrap_or("/dev/sdX");
+
+    format!(
+        "{} encrypted with BitLocker, unlock and mount it read-only first, e.g. \
+        `cryptsetup bitlkOpen {} windows && mount -o ro /dev/mapper/windows /mnt/windows` or \
+        `dislocker -V {} -u -- /mnt/dislocker && mount -o ro,loop /mnt/dislocker/dislocker-file /mnt/windows`",
+        devices.join(", "),
+        device,
+        device
+    )
+}
+
+/// Whether windows left the volume in a state other systems shouldn't trust
+pub struct VolumeState {
+    /// hiberfil.sys holds a hibernation image, also the case after a Fast Startup shutdown
+    pub hibernated: bool,
+    /// Windows didn't cleanly unmount NTFS, `None` when the device can't be read or the
+    /// flag may be the driver's own
+    pub dirty: Option<bool>,
+}
+
+impl VolumeState {
+    pub fn is_unclean(&self) -> bool {
+        self.hibernated || self.dirty == Some(true)
+    }
+}
+
+/// Checks hiberfil.sys and the dirty flag of the windows partition mounted at `win_mount`
+///
+/// Drivers set the flag themselves while a volume is mounted read-write, and mount one
+/// windows left dirty read-write only with `force`, so the flag is windows' when the
+/// volume is mounted read-only or forced
+pub fn volume_state(win_mount: &Path) -> VolumeState {
+    let dirty = mount_entry(win_mount).and_then(|(device, options)| {
+        let has_option = |name: &str| options.split(',').any(|o| o == name);
+        match is_dirty(&device) {
+            Ok(true) if !has_option("ro") && !has_option("force") => {
+                debug!(
+                    "{:?} is mounted read-write, its dirty flag may be the driver's own",
+                    device
+                );
+                None
+            }
+            Ok(dirty) => Some(dirty),
+            Err(e) => {
+                debug!("can't read the dirty flag of {:?}: {:?}", device, e);
+                None
+            }
+        }
+    });
+
+    VolumeState {
+        hibernated: is_hibernated(win_mount),
+        dirty,
+    }
+}
+
+/// Whether hiberfil.sys starts with a `hibr` or `wake` signature, windows zeroes
+/// the header after resuming
+///
+/// ## Example
+/// ```
+/// 48 49 42 52 00 00 00 00 00 00 00 00 00 00 00 00  |HIBR............|
+/// ```
+pub fn is_hibernated(win_mount: &Path) -> bool {
+    let mut signature = [0u8; 4];
+    match File::open(win_mount.join(HIBERFIL_PATH)).and_then(|mut f| f.read_exact(&mut signature)) {
+        Ok(()) => {
+            let signature = signature.to_ascii_lowercase();
+            &signature == b"hibr" || &signature == b"wake"
+        }
+        Err(e) => {
+            debug!("no hibernation image in {:?}: {:?}", win_mount, e);
+            false
+        }
+    }
+}
+
+/// Reads the dirty flag from $VOLUME_INFORMATION of the $Volume MFT record
+pub fn is_dirty(device: &Path) -> CustomResult<bool> {
+    let mut file = File::open(device).map_err(|e| e.into())?;
+    let mut boot_sector = [0u8; 512];
+    file.read_exact(&mut boot_sector).map_err(|e| e.into())?;
+    if &boot_sector[OEM_ID_OFFSET..OEM_ID_OFFSET + 8] != NTFS_OEM_ID {
+        return Err(format!("{:?} is not an NTFS volume", device).into());
+    }
+
+    let bytes_per_sector = le_u16(&boot_sector, 0x0b).unwrap_or(512) as u64;
+    let cluster_size = bytes_per_sector * boot_sector[0x0d] as u64;
+    let mft_cluster = le_u64(&boot_sector, 0x30).unwrap_or(0);
+    // Negative values are the power of two of the size in bytes
+    let record_size = match boot_sector[0x40] as i8 {
+        n if n < 0 => 1u64 << -n,
+        n => n as u64 * cluster_size,
+    };
+
+    let mut record = vec![0u8; record_size as usize];
+    file.seek(SeekFrom::Start(
+        mft_cluster * cluster_size + VOLUME_MFT_RECORD * record_size,
+    ))
+    .map_err(|e| e.into())?;
+    file.read_exact(&mut record).map_err(|e| e.into())?;
+
+    volume_flags(&mut record)
+        .map(|flags| flags & VOLUME_IS_DIRTY != 0)
+        .ok_or_else(|| format!("malformed $Volume record in {:?}", device).into())
+}
+
+/// Flags of $VOLUME_INFORMATION in a raw `FILE` record
+fn volume_flags(record: &mut [u8]) -> Option<u16> {
+    if record.get(0..4)? != b"FILE" {
+        return None;
+    }
+    apply_fixups(record)?;
+
+    let mut offset = le_u16(record, 0x14)? as usize;
+    loop {
+        let kind = le_u32(record, offset)?;
+        let length = le_u32(record, offset + 4)? as usize;
+        if kind == ATTRIBUTES_END || length == 0 {
+            return None;
+        }
+
+        // resident attribute, content is 8 reserved bytes, version and flags
+        if kind == VOLUME_INFORMATION && *record.get(offset + 8)? == 0 {
+            let content = offset + le_u16(record, offset + 0x14)? as usize;
+            return le_u16(record, content + 10);
+        }
+        offset += length;
+    }
+}
+
+/// Puts back the last two bytes of every 512-byte stride, which were replaced by the
+/// update sequence number when the record was written
+fn apply_fixups(record: &mut [u8]) -> Option<()> {
+    let usa_offset = le_u16(record, 0x04)? as usize;
+    let usa_count = le_u16(record, 0x06)? as usize;
+    let usn = record.get(usa_offset..usa_offset + 2)?.to_vec();
+
+    for i in 1..usa_count {
+        let sector_end = i * FIXUP_STRIDE - 2;
+        if record.get(sector_end..sector_end + 2)? != usn.as_slice() {
+            return None;
+        }
+        let fixup = record
+            .get(usa_offset + i * 2..usa_offset + i * 2 + 2)?
+            .to_vec();
+        record[sector_end..sector_end + 2].copy_from_slice(&fixup);
+    }
+    Some(())
+}
+
+/// Whether `mount` is mounted with the `ro` option
+///
+/// ## Example
+/// ```
+/// /dev/nvme0n1p3 /mnt/windows ntfs3 ro,relatime,uid=0,gid=0 0 0
+/// ```
+pub fn is_read_only(mount: &Path) -> bool {
+    mount_entry(mount).is_some_and(|(_, options)| options.split(',').any(|o| o == "ro"))
+}
+
+/// Device and options of the topmost mount at `mount`, e.g. `/mnt/windows` ->
+/// `("/dev/nvme0n1p3", "ro,relatime,uid=0,gid=0")`
+fn mount_entry(mount: &Path) -> Option<(PathBuf, String)> {
+    read_to_string("/proc/mounts")
+        .ok()?
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split(' ');
+            Some((fields.next()?, fields.next()?, fields.nth(1)?))
+        })
+        .rfind(|(_, mnt_p, _)| Path::new(mnt_p) == mount)
+        .map(|(device, _, options)| (PathBuf::from(device), options.to_string()))
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `FILE` record of `size` bytes with a filler attribute and $VOLUME_INFORMATION,
+    /// whose flags end the first stride so they only read right after the fixups
+    fn volume_record(size: usize, flags: u16) -> Vec<u8> {
+        let mut record = vec![0u8; size];
+        let usa_offset = 0x30;
+        let usa_count = size / FIXUP_STRIDE + 1;
+        let attributes = (usa_offset + usa_count * 2 + 7) & !7;
+        record[0..4].copy_from_slice(b"FILE");
+        record[0x04..0x06].copy_from_slice(&(usa_offset as u16).to_le_bytes());
+        record[0x06..0x08].copy_from_slice(&(usa_count as u16).to_le_bytes());
+        record[0x14..0x16].copy_from_slice(&(attributes as u16).to_le_bytes());
+
+        // $STANDARD_INFORMATION stand-in, sized to put the flags at 510
+        let info = FIXUP_STRIDE - 2 - 0x18 - 10;
+        record[attributes..attributes + 4].copy_from_slice(&0x10u32.to_le_bytes());
+        record[attributes + 4..attributes + 8]
+            .copy_from_slice(&((info - attributes) as u32).to_le_bytes());
+
+        record[info..info + 4].copy_from_slice(&VOLUME_INFORMATION.to_le_bytes());
+        record[info + 4..info + 8].copy_from_slice(&0x28u32.to_le_bytes());
+        record[info + 0x14..info + 0x16].copy_from_slice(&0x18u16.to_le_bytes());
+        record[info + 0x18 + 8] = 3;
+        record[info + 0x18 + 9] = 1;
+        record[info + 0x18 + 10..info + 0x18 + 12].copy_from_slice(&flags.to_le_bytes());
+        record[info + 0x28..info + 0x2c].copy_from_slice(&ATTRIBUTES_END.to_le_bytes());
+
+        // what windows writes: the end of every stride saved in the array, then replaced
+        let usn = [0x42, 0x00];
+        record[usa_offset..usa_offset + 2].copy_from_slice(&usn);
+        for i in 1..usa_count {
+            let end = i * FIXUP_STRIDE - 2;
+            let saved = [record[end], record[end + 1]];
+            record[usa_offset + i * 2..usa_offset + i * 2 + 2].copy_from_slice(&saved);
+            record[end..end + 2].copy_from_slice(&usn);
+        }
+        record
+    }
+
+    #[test]
+    fn reads_volume_flags() {
+        assert_eq!(
+            volume_flags(&mut volume_record(1024, VOLUME_IS_DIRTY)),
+            Some(1)
+        );
+        assert_eq!(volume_flags(&mut volume_record(1024, 0)), Some(0));
+        // 4096-byte sectors still have fixups every 512 bytes
+        assert_eq!(
+            volume_flags(&mut volume_record(4096, VOLUME_IS_DIRTY | 0x4000)),
+            Some(0x4001)
+        );
+
+        let mut not_file = volume_record(1024, 0);
+        not_file[0..4].copy_from_slice(b"BAAD");
+        assert_eq!(volume_flags(&mut not_file), None);
+    }
+
+    #[test]
+    fn refuses_torn_records() {
+        for size in [1024, 4096] {
+            let mut record = volume_record(size, VOLUME_IS_DIRTY);
+            record[size - 2] ^= 0xff;
+            assert_eq!(apply_fixups(&mut record), None, "{} bytes", size);
+        }
+    }
+
+    fn boot_sector(oem_id: &[u8; 8]) -> tempfile::NamedTempFile {
+        let mut sector = vec![0xeb, 0x58, 0x90];
+        sector.extend_from_slice(oem_id);
+        sector.resize(512, 0);
+        sector[510..].copy_from_slice(&[0x55, 0xaa]);
+
+        let file = tempfile::NamedTempFile::new().expect("temp file");
+        std::fs::write(file.path(), sector).expect("write boot sector");
+        file
+    }
+
+    #[test]
+    fn detects_bitlocker_boot_sectors() {
+        assert!(is_bitlocker(boot_sector(BITLOCKER_OEM_ID).path()));
+        assert!(!is_bitlocker(boot_sector(NTFS_OEM_ID).path()));
+
+        let truncated = tempfile::NamedTempFile::new().expect("temp file");
+        std::fs::write(truncated.path(), b"\xeb\x58\x90-FVE").expect("write boot sector");
+        assert!(!is_bitlocker(truncated.path()));
+        assert!(!is_bitlocker(Path::new("/nonexistent/bt-dualboot")));
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use inquire::Confirm;
+use log::{debug, error, warn};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    bluez::{self, BluezDir},
+    bt_device::uni_bt_device::{UniBtAdapter, UniBtDevice},
+    cli::{Source, Target},
+    freshness::Freshness,
+    hooks::Hooks,
+    json_file::JsonFile,
+    ledger::Direction,
+    macos::MacosPlist,
+    report,
+    utils::format_time,
+    windows::WindowsRegistry,
+    CustomResult, Interaction,
+};
+
+/// Reads pairings of one system, e.g. the registry of a windows partition
+pub trait KeySource {
+    fn read(&mut self) -> CustomResult<SourceKeys>;
+}
+
+/// Writes pairings into one system, e.g. a linux installation, and reports what
+/// happened to every device
+pub trait KeySink {
+    /// System the keys end up in, the `linux` of `windows-to-linux`
+    fn system(&self) -> System;
+
+    /// Root, partition or file the keys go into, names the sink in reports
+    fn target(&self) -> String;
+
+    fn write(&mut self, keys: &SourceKeys, policy: &Policy) -> CustomResult<report::RootReport>;
+}
+
+/// Pairings read by a `KeySource`
+#[derive(Clone)]
+pub struct SourceKeys {
+    /// Mount point of the source partition, root of a linux installation or path
+    /// of a file
+    pub source: String,
+    pub system: System,
+    pub devices: Vec<UniBtDevice>,
+    /// Local keys of the adapters, only windows and linux keep them
+    pub adapters: Vec<UniBtAdapter>,
+}
+
+/// Keys which were already read, e.g. the devices picked in the TUI
+impl KeySource for SourceKeys {
+    fn read(&mut self) -> CustomResult<SourceKeys> {
+        Ok(self.clone())
+    }
+}
+
+/// Where keys are read from or written to, e.g. `windows` in `windows-to-linux`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum System {
+    Windows,
+    Macos,
+    Linux,
+    /// Keys saved with `export`
+    File,
+}
+
+/// How sinks treat pairings which already exist
+#[derive(Default)]
+pub struct Policy {
+    /// Overwrite pairings even when they were used after the source ones
+    pub force: bool,
+    /// Also copy name, class and device id
+    pub fill_metadata: bool,
+    /// Decides devices whose keys differ on both sides
+    pub conflict: ConflictPolicy,
+    /// Overrides of `conflict` by device address, e.g. `4C:87:5D:26:DC:9F`
+    pub device_conflicts: HashMap<String, ConflictPolicy>,
+}
+
+/// What to do with a device paired on both sides with different keys. The source is
+/// the system the keys come from, the target the one they would be written into
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConflictPolicy {
+    /// Always write the source keys
+    PreferSource,
+    /// Always keep the keys already in the target
+    PreferTarget,
+    /// Keys of the side which used the device last win, the source ones when
+    /// either side has no timestamps
+    #[default]
+    Newest,
+    /// Prompt for every such device, `--yes` overwrites them and without prompts the
+    /// existing keys are kept
+    Ask,
+    /// Leave such devices alone, they are reported as skipped
+    Skip,
+}
+
+impl Policy {
+    /// Policy deciding the device with `address`, `force` beats any configured one
+    pub fn conflict_for(&self, address: &str) -> ConflictPolicy {
+        if self.force {
+            return ConflictPolicy::PreferSource;
+        }
+
+        self.device_conflicts
+            .get(&address.to_uppercase())
+            .copied()
+            .unwrap_or(self.conflict)
+    }
+}
+
+/// Whether keys of a device which differ between the two systems of `direction` may
+/// be replaced in its target, `freshness` is `None` when either side has no timestamps
+pub fn resolve_conflict(
+    label: &str,
+    conflict: ConflictPolicy,
+    freshness: Option<Freshness>,
+    direction: Direction,
+    interaction: &Interaction,
+) -> CustomResult<bool> {
+    let Direction { from, to } = direction;
+    // linux times are the info files, the other side's come with the device
+    let used = freshness.map(|f| match to {
+        System::Linux => (f.windows, f.linux),
+        _ => (f.linux, f.windows),
+    });
+    let message = match used {
+        Some((source, target)) => format!(
+            "keys of {} differ, used in {} at {} and in {} at {}",
+            label,
+            to,
+            format_time(target),
+            from,
+            format_time(source)
+        ),
+        None => format!("keys of {} differ", label),
+    };
+
+    match conflict {
+        ConflictPolicy::PreferSource => Ok(true),
+        ConflictPolicy::PreferTarget => {
+            warn!(
+                "{}, keeping the {} pairing because of {}",
+                message, to, conflict
+            );
+            Ok(false)
+        }
+        // sinks leave skipped devices alone without asking
+        ConflictPolicy::Skip => Ok(false),
+        ConflictPolicy::Newest => match used {
+            Some((source, target)) if target > source => {
+                warn!("{}, keeping the newer {} pairing", message, to);
+                Ok(false)
+            }
+            _ => Ok(true),
+        },
+        ConflictPolicy::Ask if interaction.assume_yes => Ok(true),
+        ConflictPolicy::Ask if !interaction.interactive => {
+            warn!("{}, keeping the {} pairing without prompts", message, to);
+            Ok(false)
+        }
+        ConflictPolicy::Ask => Confirm::new(&format!("{}. Overwrite the {} pairing?", message, to))
+            .with_default(false)
+            .prompt()
+            .map_err(|e| e.into()),
+    }
+}
+
+/// Why a device whose keys differ was left alone in `target`
+pub fn kept_reason(conflict: ConflictPolicy, target: System) -> String {
+    match conflict {
+        ConflictPolicy::Newest => format!("{} pairing is newer, use --force to overwrite", target),
+        ConflictPolicy::PreferTarget => format!("{} keys are preferred", target),
+        ConflictPolicy::Ask => "overwrite not confirmed".to_string(),
+        ConflictPolicy::Skip => "keys differ, left alone by the skip policy".to_string(),
+        ConflictPolicy::PreferSource => "source keys are preferred".to_string(),
+    }
+}
+
+/// Where `--source` takes the keys from, `root` is the linux installation for
+/// `--source linux` and `input` the file for `--source json`
+pub fn key_source<'a>(
+    source: Source,
+    root: &Path,
+    input: Option<PathBuf>,
+    reg_export: Option<PathBuf>,
+    interaction: &'a Interaction,
+) -> Box<dyn KeySource + 'a> {
+    match source {
+        Source::Windows => Box::new(WindowsRegistry {
+            reg_export,
+            interaction,
+            root: root.to_path_buf(),
+        }),
+        Source::Macos => Box::new(MacosPlist { interaction }),
+        Source::Linux => Box::new(BluezDir {
+            root: root.to_path_buf(),
+            interaction,
+        }),
+        Source::Json => Box::new(JsonFile {
+            path: input.unwrap_or_else(|| PathBuf::from("-")),
+        }),
+    }
+}
+
+/// Where `--target` writes the keys of `source`, every linux installation of `roots` or
+/// the chosen detected ones. The windows registry uses the first root, or `/`, for
+/// device names and its ledger
+pub fn key_sinks<'a>(
+    source: Source,
+    target: Target,
+    roots: &[PathBuf],
+    reg_export: Option<PathBuf>,
+    interaction: &'a Interaction,
+) -> CustomResult<Vec<Box<dyn KeySink + 'a>>> {
+    match (source, target) {
+        (Source::Linux, Target::Linux) | (Source::Windows, Target::Windows) => {
+            Err("--source and --target are the same system".into())
+        }
+        (_, Target::Windows) => Ok(vec![Box::new(WindowsRegistry {
+            reg_export,
+            interaction,
+            root: roots.first().cloned().unwrap_or_else(|| PathBuf::from("/")),
+        })]),
+        (_, Target::Linux) => {
+            let roots = bluez::choose_linux_roots(roots, interaction)?;
+            if roots.is_empty() {
+                warn!("no linux installations with bluetooth pairings");
+            }
+            Ok(roots
+                .into_iter()
+                .map(|root| -> Box<dyn KeySink> { Box::new(BluezDir { root, interaction }) })
+                .collect())
+        }
+    }
+}
+
+/// Reads `source` once and writes its keys into every sink in order, `hooks` run
+/// before the first sink and after the last one. A sink which fails is recorded in
+/// the report and doesn't keep the others from writing, `post_sync` runs whenever
+/// `pre_sync` succeeded
+pub fn sync(
+    source: &mut dyn KeySource,
+    sinks: &mut [Box<dyn KeySink + '_>],
+    policy: &Policy,
+    hooks: &Hooks,
+) -> CustomResult<report::SyncReport> {
+    let keys = source.read()?;
+    debug!(
+        "read {} device(s) from {} {}",
+        keys.devices.len(),
+        keys.system,
+        keys.source
+    );
+
+    let Some(to) = sinks.first().map(|s| s.system()) else {
+        return Ok(report::SyncReport::default());
+    };
+    hooks.pre_sync(&keys, to)?;
+
+    let mut report = report::SyncReport::default();
+    for sink in sinks.iter_mut() {
+        match sink.write(&keys, policy) {
+            Ok(root) => report.roots.push(root),
+            Err(e) => {
+                error!("can't write keys into {}: {:?}", sink.target(), e);
+                report.errors.push(report::SinkError {
+                    target: sink.target(),
+                    error: format!("{:?}", e),
+                });
+            }
+        }
+    }
+
+    hooks.post_sync(&keys, to, &report);
+    Ok(report)
+}
+
+impl std::fmt::Display for System {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            System::Windows => write!(f, "windows"),
+            System::Macos => write!(f, "macos"),
+            System::Linux => write!(f, "linux"),
+            System::File => write!(f, "file"),
+        }
+    }
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::PreferSource => write!(f, "prefer-source"),
+            ConflictPolicy::PreferTarget => write!(f, "prefer-target"),
+            ConflictPolicy::Newest => write!(f, "newest"),
+            ConflictPolicy::Ask => write!(f, "ask"),
+            ConflictPolicy::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefer-source" => Ok(ConflictPolicy::PreferSource),
+            "prefer-target" => Ok(ConflictPolicy::PreferTarget),
+            "newest" => Ok(ConflictPolicy::Newest),
+            "ask" => Ok(ConflictPolicy::Ask),
+            "skip" => Ok(ConflictPolicy::Skip),
+            _ => Err(format!(
+                "unknown conflict policy {:?}, expected prefer-source, prefer-target, newest, ask or skip",
+                s
+            )),
+        }
+    }
+}
+
+impl Serialize for ConflictPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl std::str::FromStr for System {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "windows" => Ok(System::Windows),
+            "macos" => Ok(System::Macos),
+            "linux" => Ok(System::Linux),
+            "file" => Ok(System::File),
+            _ => Err(format!("unknown system {:?}", s)),
+        }
+    }
+}
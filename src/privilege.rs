@@ -0,0 +1,103 @@
+use std::{
+    env,
+    ffi::{CString, OsString},
+    io::ErrorKind,
+    os::unix::{ffi::OsStrExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::info;
+
+use crate::{CustomResult, Interaction};
+
+/// How a command uses a path
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Fails with a hint to run as root when the current user can't use every path of
+/// `needs` the way it's used. Missing paths are left for the command to report
+pub fn check(needs: &[(PathBuf, Access)]) -> CustomResult<()> {
+    match denied(needs) {
+        Some(denied) => Err(format!("{}, run it with sudo or pass --elevate", denied).into()),
+        None => Ok(()),
+    }
+}
+
+/// Checks `needs` and, when the current user lacks access and `elevate` is set, runs
+/// the same command again through sudo in a terminal or pkexec otherwise. Doesn't
+/// return when the command is run again
+pub fn ensure(
+    needs: &[(PathBuf, Access)],
+    elevate: bool,
+    interaction: &Interaction,
+) -> CustomResult<()> {
+    if !elevate {
+        return check(needs);
+    }
+    let Some(denied) = denied(needs) else {
+        return Ok(());
+    };
+
+    let tool = if interaction.interactive {
+        "sudo"
+    } else {
+        "pkexec"
+    };
+    let Some(tool_path) = find_in_path(tool) else {
+        return Err(format!("{} and {} is not installed", denied, tool).into());
+    };
+
+    // pkexec wants an absolute path of the program
+    let exe = env::current_exe().map_err(|e| e.into())?;
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    info!("running {:?} again with {}", exe, tool);
+    let error = Command::new(tool_path).arg(exe).args(args).exec();
+    Err(format!("can't run {}: {:?}", tool, error).into())
+}
+
+/// "reading \"/var/lib/bluetooth\" needs root" for paths of `needs` the current user
+/// can't use. Missing paths are left for the command to report
+fn denied(needs: &[(PathBuf, Access)]) -> Option<String> {
+    let denied: Vec<_> = needs
+        .iter()
+        .filter(|(path, access)| !can_access(path, *access))
+        .map(|(path, access)| match access {
+            Access::Read => format!("reading {:?}", path),
+            Access::Write => format!("writing {:?}", path),
+        })
+        .collect();
+    if denied.is_empty() {
+        return None;
+    }
+    Some(format!("{} needs root", denied.join(" and ")))
+}
+
+/// Whether access(2) lets the process use `path` with its effective ids, dirs also
+/// need to be entered
+fn can_access(path: &Path, access: Access) -> bool {
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return true;
+    };
+    let mut mode = match access {
+        Access::Read => libc::R_OK,
+        Access::Write => libc::W_OK,
+    };
+    if path.is_dir() {
+        mode |= libc::X_OK;
+    }
+
+    // SAFETY: c_path is a NUL-terminated string which outlives the call
+    let result =
+        unsafe { libc::faccessat(libc::AT_FDCWD, c_path.as_ptr(), mode, libc::AT_EACCESS) };
+    result == 0 || std::io::Error::last_os_error().kind() != ErrorKind::PermissionDenied
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
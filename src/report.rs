@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::{pipeline::ConflictPolicy, CustomResult};
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FATAL: i32 = 1;
+pub const EXIT_PARTIAL: i32 = 2;
+pub const EXIT_NOTHING_TO_DO: i32 = 3;
+
+/// Result of a whole sync run, one entry per linux installation, or the windows
+/// partition when syncing from linux
+#[derive(Serialize, Default)]
+pub struct SyncReport {
+    pub roots: Vec<RootReport>,
+    /// Sinks which failed as a whole, their devices aren't in `roots`
+    pub errors: Vec<SinkError>,
+}
+
+#[derive(Serialize)]
+pub struct SinkError {
+    /// Root, partition or file the keys were to be written into
+    pub target: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct RootReport {
+    pub root: PathBuf,
+    /// Only present when some info file or the windows registry was backed up
+    pub backup_dir: Option<PathBuf>,
+    /// Adapters whose local IRK was replaced with the one of windows
+    pub identities: Vec<String>,
+    pub devices: Vec<DeviceReport>,
+}
+
+#[derive(Serialize)]
+pub struct DeviceReport {
+    pub adapter: String,
+    pub address: String,
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    /// Conflict policy which decided the outcome, only for devices whose keys differed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<ConflictPolicy>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    Updated,
+    Unchanged,
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+impl SyncReport {
+    fn outcomes(&self) -> impl Iterator<Item = &Outcome> {
+        self.roots
+            .iter()
+            .flat_map(|r| r.devices.iter().map(|d| &d.outcome))
+    }
+
+    fn count(&self, matches: fn(&Outcome) -> bool) -> usize {
+        self.outcomes().filter(|o| matches(o)).count()
+    }
+
+    /// 0 when something was updated and nothing failed, 2 when some devices or sinks
+    /// failed, 1 when everything which was tried failed, 3 when there was nothing to
+    /// update
+    pub fn exit_code(&self) -> i32 {
+        let updated = self.count(|o| matches!(o, Outcome::Updated));
+        let unchanged = self.count(|o| matches!(o, Outcome::Unchanged));
+        let failed = self.count(|o| matches!(o, Outcome::Failed { .. })) + self.errors.len();
+
+        match (updated + unchanged, failed) {
+            (_, 0) if updated > 0 => EXIT_SUCCESS,
+            (_, 0) => EXIT_NOTHING_TO_DO,
+            (0, _) => EXIT_FATAL,
+            _ => EXIT_PARTIAL,
+        }
+    }
+
+    /// Prints one line per device and the totals
+    ///
+    /// ## Example
+    /// ```
+    /// ROOT  ADAPTER            DEVICE                           RESULT
+    /// /     C0:FB:F9:60:1C:13  WH-1000XM4 (4C:87:5D:26:DC:9F)   updated (newest)
+    /// /     C0:FB:F9:60:1C:13  00:1A:7D:DA:71:13                skipped: not paired in linux
+    /// updated 1, unchanged 0, skipped 1, failed 0
+    /// ```
+    pub fn print_table(&self) {
+        let rows: Vec<_> = self
+            .roots
+            .iter()
+            .flat_map(|r| {
+                r.devices.iter().map(|d| {
+                    let device = match d.name.as_ref() {
+                        Some(name) => format!("{} ({})", name, d.address),
+                        None => d.address.clone(),
+                    };
+                    let outcome = match d.policy {
+                        Some(policy) => format!("{} ({})", d.outcome, policy),
+                        None => d.outcome.to_string(),
+                    };
+                    (r.root.display().to_string(), &d.adapter, device, outcome)
+                })
+            })
+            .collect();
+
+        let root_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max(4);
+        let device_width = rows.iter().map(|r| r.2.len()).max().unwrap_or(0).max(6);
+
+        println!(
+            "{:root_width$}  {:17}  {:device_width$}  RESULT",
+            "ROOT", "ADAPTER", "DEVICE"
+        );
+        for (root, adapter, device, outcome) in rows {
+            println!(
+                "{:root_width$}  {:17}  {:device_width$}  {}",
+                root, adapter, device, outcome
+            );
+        }
+
+        println!(
+            "updated {}, unchanged {}, skipped {}, failed {}",
+            self.count(|o| matches!(o, Outcome::Updated)),
+            self.count(|o| matches!(o, Outcome::Unchanged)),
+            self.count(|o| matches!(o, Outcome::Skipped { .. })),
+            self.count(|o| matches!(o, Outcome::Failed { .. })),
+        );
+        for error in self.errors.iter() {
+            println!("failed to write into {}: {}", error.target, error.error);
+        }
+        for root in self.roots.iter() {
+            for adapter in root.identities.iter() {
+                println!(
+                    "replaced local IRK of adapter {} in {}",
+                    adapter,
+                    root.root.display()
+                );
+            }
+            if let Some(backup_dir) = root.backup_dir.as_ref() {
+                println!(
+                    "backups of {} in {}",
+                    root.root.display(),
+                    backup_dir.display()
+                );
+            }
+        }
+    }
+
+    pub fn print_json(&self) -> CustomResult<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.into())?;
+        println!("{}", json);
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Updated => write!(f, "updated"),
+            Outcome::Unchanged => write!(f, "unchanged"),
+            Outcome::Skipped { reason } => write!(f, "skipped: {}", reason),
+            Outcome::Failed { error } => write!(f, "failed: {}", error),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    env::current_exe,
+    fs::{remove_file, File},
+    io::Write,
+    path::Path,
+    process::Command,
+};
+
+use log::{info, warn};
+
+use crate::{config::Config, resolve_partition, CustomResult};
+
+const SERVICE_NAME: &str = "bt-dualboot-sync.service";
+pub const SERVICE_DIR: &str = "/etc/systemd/system";
+
+/// Writes and enables a oneshot unit pulled in by bluetooth.service and ordered
+/// before it, so bluetoothd starts with the synced keys
+pub fn install(config_path: Option<&Path>, config: &Config) -> CustomResult<()> {
+    if config.partition().is_none() {
+        warn!("no Partition in the config, the boot sync fails if several windows partitions are mounted");
+    }
+
+    let exe = current_exe().map_err(|e| e.into())?;
+    let mut exec_start = format!("{}", exe.display());
+    if let Some(config_path) = config_path {
+        let config_path = config_path.canonicalize().map_err(|e| e.into())?;
+        exec_start.push_str(&format!(" --config {}", config_path.display()));
+    }
+    exec_start.push_str(" --journal --quiet sync --non-interactive --yes");
+
+    let mount = config.partition().and_then(|p| required_mount(&p));
+    let unit = unit_text(&exec_start, mount.as_deref());
+
+    let unit_path = Path::new(SERVICE_DIR).join(SERVICE_NAME);
+    let mut file = File::create(&unit_path).map_err(|e| e.into())?;
+    file.write_all(unit.as_bytes()).map_err(|e| e.into())?;
+    info!("wrote {:?}", unit_path);
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", SERVICE_NAME])?;
+    println!("installed {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// Unit running `exec_start`, after `mount` is mounted when given
+fn unit_text(exec_start: &str, mount: Option<&str>) -> String {
+    let mut unit = String::from(
+        "[Unit]\n\
+         Description=Sync bluetooth pairing keys from windows\n\
+         Before=bluetooth.service\n\
+         After=local-fs.target\n",
+    );
+    if let Some(mount) = mount {
+        unit.push_str(&format!("RequiresMountsFor={}\n", mount));
+    }
+    unit.push_str(&format!(
+        "\n[Service]\n\
+         Type=oneshot\n\
+         ExecStart={}\n\
+         \n[Install]\n\
+         WantedBy=bluetooth.service\n",
+        exec_start
+    ));
+    unit
+}
+
+/// Path for `RequiresMountsFor`, which only takes absolute ones. A UUID or label is
+/// replaced with where the partition is mounted now
+fn required_mount(partition: &str) -> Option<String> {
+    if Path::new(partition).is_absolute() {
+        return Some(partition.to_string());
+    }
+
+    match resolve_partition(partition) {
+        Ok(mount) => Some(mount),
+        Err(e) => {
+            warn!(
+                "{:?}, the boot sync won't wait for {} to be mounted",
+                e, partition
+            );
+            None
+        }
+    }
+}
+
+pub fn uninstall() -> CustomResult<()> {
+    let unit_path = Path::new(SERVICE_DIR).join(SERVICE_NAME);
+    if !unit_path.exists() {
+        return Err(format!("{} is not installed", SERVICE_NAME).into());
+    }
+
+    systemctl(&["disable", SERVICE_NAME])?;
+    remove_file(&unit_path).map_err(|e| e.into())?;
+    systemctl(&["daemon-reload"])?;
+    println!("uninstalled {}", SERVICE_NAME);
+    Ok(())
+}
+
+fn systemctl(args: &[&str]) -> CustomResult<()> {
+    let status = Command::new("systemctl")
+        .args(args)
+        .status()
+        .map_err(|e| e.into())?;
+
+    if !status.success() {
+        return Err(format!("systemctl {} failed with {}", args.join(" "), status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_absolute_mounts() {
+        let exec_start = "/usr/bin/bt-dualboot-rs sync";
+
+        let mount = required_mount("/mnt/windows");
+        assert_eq!(mount.as_deref(), Some("/mnt/windows"));
+        let unit = unit_text(exec_start, mount.as_deref());
+        assert!(unit.contains("After=local-fs.target\nRequiresMountsFor=/mnt/windows\n"));
+        assert!(unit.contains("ExecStart=/usr/bin/bt-dualboot-rs sync\n"));
+
+        // a label of no partition has no mount point to wait for
+        let mount = required_mount("bt-dualboot-no-such-label");
+        assert_eq!(mount, None);
+        assert!(!unit_text(exec_start, mount.as_deref()).contains("RequiresMountsFor"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{collections::HashSet, path::Path};
+
+use log::error;
+
+use crate::{
+    bluez::{build_linux_device, get_linux_devices},
+    bt_device::{linux_bt_device, uni_bt_device::UniBtDevice},
+    ledger::{changed_sections, SectionChange},
+    names::NameIndex,
+    windows::get_reged_bt_devices,
+    CustomResult, Interaction, LINUX_BT_DIR,
+};
+
+pub const EXIT_IN_SYNC: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_OUT_OF_SYNC: i32 = 2;
+
+pub enum DeviceStatus {
+    InSync,
+    /// Sections as they are in linux (`before`) and as a sync from windows writes them
+    KeysDiffer(Vec<SectionChange>),
+    OnlyWindows,
+    OnlyLinux,
+    AdapterMissing,
+}
+
+pub struct DeviceReport {
+    pub adapter: String,
+    /// "WH-1000XM4 (4C:87:5D:26:DC:9F)" or just the address when the name is unknown
+    pub label: String,
+    pub status: DeviceStatus,
+}
+
+/// Prints pairing state of every device and returns the exit code
+pub fn run(reg_export: Option<&Path>, interaction: &Interaction) -> i32 {
+    let reports = match get_status(reg_export, interaction) {
+        Ok(reports) => reports,
+        Err(e) => {
+            error!("can't get status: {:?}", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    for report in reports.iter() {
+        println!("{} {} {}", report.adapter, report.label, report.status);
+    }
+
+    if reports
+        .iter()
+        .all(|r| matches!(r.status, DeviceStatus::InSync))
+    {
+        EXIT_IN_SYNC
+    } else {
+        EXIT_OUT_OF_SYNC
+    }
+}
+
+pub fn get_status(
+    reg_export: Option<&Path>,
+    interaction: &Interaction,
+) -> CustomResult<Vec<DeviceReport>> {
+    let win_devices = get_reged_bt_devices(reg_export, interaction)?.devices;
+    let linux_devices = get_linux_devices(Path::new("/"))?;
+    let names = NameIndex::load(Path::new("/"));
+
+    let mut seen = HashSet::new();
+    let mut reports: Vec<_> = win_devices
+        .iter()
+        .map(|win_dev| {
+            let adapter = linux_bt_device::BtAddress::from(win_dev.parent_address.clone()).0;
+            let address = linux_bt_device::BtAddress::from(win_dev.address.clone()).0;
+
+            let linux_dev = linux_devices
+                .iter()
+                .find(|(l_adapter, l_address, _)| *l_adapter == adapter && *l_address == address);
+
+            let status = if let Some((_, _, linux_dev)) = linux_dev {
+                seen.insert((adapter.clone(), address.clone()));
+                compare_devices(linux_dev, win_dev)
+            } else if !Path::new("/").join(LINUX_BT_DIR).join(&adapter).exists() {
+                DeviceStatus::AdapterMissing
+            } else {
+                DeviceStatus::OnlyWindows
+            };
+
+            DeviceReport {
+                label: names.label(win_dev),
+                adapter,
+                status,
+            }
+        })
+        .collect();
+
+    reports.extend(
+        linux_devices
+            .into_iter()
+            .filter(|(adapter, address, _)| !seen.contains(&(adapter.clone(), address.clone())))
+            .map(|(adapter, address, _)| DeviceReport {
+                label: names.address_label(&adapter, &address),
+                adapter,
+                status: DeviceStatus::OnlyLinux,
+            }),
+    );
+
+    Ok(reports)
+}
+
+/// Key sections of `linux_dev` matched by name with the ones a sync of `win_dev` writes
+pub fn compare_devices(
+    linux_dev: &linux_bt_device::BtDevice,
+    win_dev: &UniBtDevice,
+) -> DeviceStatus {
+    let diffs = changes_of_sync(linux_dev, win_dev);
+    if diffs.is_empty() {
+        DeviceStatus::InSync
+    } else {
+        DeviceStatus::KeysDiffer(diffs)
+    }
+}
+
+/// What a sync of `win_dev` would change in `linux_dev`, the key sections, with
+/// `Authenticated` and `EncSize` of long term keys, and `General.AddressType`. Bluez
+/// ignores LE keys of another address type, so it is shown as is instead of a
+/// fingerprint
+pub fn changes_of_sync(
+    linux_dev: &linux_bt_device::BtDevice,
+    win_dev: &UniBtDevice,
+) -> Vec<SectionChange> {
+    let expected = build_linux_device(linux_dev.clone(), win_dev, false);
+    let mut changes = changed_sections(&linux_dev.key_sections(), &expected.key_sections());
+
+    let address_type =
+        |d: &linux_bt_device::BtDevice| d.general.as_ref().and_then(|g| g.address_type.clone());
+    let (before, after) = (address_type(linux_dev), address_type(&expected));
+    if before != after {
+        changes.push(SectionChange {
+            section: "AddressType".to_string(),
+            before,
+            after,
+        });
+    }
+
+    changes
+}
+
+impl std::fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceStatus::InSync => write!(f, "in sync"),
+            DeviceStatus::KeysDiffer(diffs) => {
+                write!(f, "keys differ")?;
+                let missing = "missing".to_string();
+                for diff in diffs {
+                    write!(
+                        f,
+                        " [{}: linux {} windows {}]",
+                        diff.section,
+                        diff.before.as_ref().unwrap_or(&missing),
+                        diff.after.as_ref().unwrap_or(&missing)
+                    )?;
+                }
+                Ok(())
+            }
+            DeviceStatus::OnlyWindows => write!(f, "only in windows"),
+            DeviceStatus::OnlyLinux => write!(f, "only in linux"),
+            DeviceStatus::AdapterMissing => write!(f, "adapter missing"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use super::*;
+
+    #[test]
+    fn compares_sections_by_name() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dual-mode");
+        let interaction = Interaction {
+            partition: None,
+            interactive: false,
+            assume_yes: false,
+        };
+        let win_keys = get_reged_bt_devices(Some(&fixture.join("export.reg")), &interaction)
+            .expect("readable export");
+        let win_dev = win_keys
+            .devices
+            .iter()
+            .find(|d| linux_bt_device::BtAddress::from(d.address.clone()).0 == "A0:E9:DB:0C:2B:4E")
+            .expect("device in the export");
+        let info =
+            read_to_string(fixture.join("expected/C0:FB:F9:60:1C:13/A0:E9:DB:0C:2B:4E/info"))
+                .expect("readable info file");
+        let status = |info: &str| {
+            compare_devices(&serde_ini::from_str(info).expect("parsable info"), win_dev)
+        };
+
+        assert!(matches!(status(&info), DeviceStatus::InSync));
+
+        // a Secure Connections key moves to another section, sections are matched by
+        // name so it shows up as removed from one and added to the other
+        let legacy = info.replace("[PeripheralLongTermKey]", "[LongTermKey]");
+        let DeviceStatus::KeysDiffer(diffs) = status(&legacy) else {
+            panic!("key in another section");
+        };
+        let sections: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.section.as_str(), d.before.is_some(), d.after.is_some()))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                ("LongTermKey", true, false),
+                ("PeripheralLongTermKey", false, true)
+            ]
+        );
+
+        let DeviceStatus::KeysDiffer(diffs) = status(&info.replace("EncSize=16", "EncSize=7"))
+        else {
+            panic!("key size differs");
+        };
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].section, "PeripheralLongTermKey");
+
+        let DeviceStatus::KeysDiffer(diffs) =
+            status(&info.replace("Authenticated=3", "Authenticated=0"))
+        else {
+            panic!("authentication differs");
+        };
+        assert_eq!(diffs[0].section, "PeripheralLongTermKey");
+
+        let DeviceStatus::KeysDiffer(diffs) =
+            status(&info.replace("AddressType=public", "AddressType=static"))
+        else {
+            panic!("address type differs");
+        };
+        let sections: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.section.as_str(), d.before.as_deref(), d.after.as_deref()))
+            .collect();
+        assert_eq!(sections, [("AddressType", Some("static"), Some("public"))]);
+    }
+}
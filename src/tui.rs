@@ -0,0 +1,452 @@
+use std::path::{Path, PathBuf};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    bluez::BluezDir,
+    bt_device::{linux_bt_device, uni_bt_device::UniBtDevice},
+    hooks::Hooks,
+    ledger::{self, SectionChange},
+    names::NameIndex,
+    pipeline::{self, ConflictPolicy, KeySink, KeySource, Policy, SourceKeys},
+    report,
+    status::{self, DeviceStatus},
+    utils::format_time,
+    windows::{self, WindowsRegistry},
+    CustomResult, Interaction, LINUX_BT_DIR,
+};
+
+/// What to do with one device when the plan is applied
+#[derive(Clone, Copy, PartialEq)]
+enum Plan {
+    Skip,
+    ToLinux,
+    ToWindows,
+}
+
+/// One line of the device table, a device paired in windows, linux or both
+struct DeviceRow {
+    adapter: String,
+    address: String,
+    label: String,
+    windows: Option<UniBtDevice>,
+    linux: Option<(linux_bt_device::BtDevice, UniBtDevice)>,
+    plan: Plan,
+}
+
+/// Lists devices of windows and the linux installation at `root`, lets the user pick a
+/// direction per device and applies the plan after leaving the full-screen view.
+/// Returns the exit code of `sync`
+pub fn run(
+    root: &Path,
+    reg_export: Option<&Path>,
+    interaction: &Interaction,
+    hooks: &Hooks,
+) -> CustomResult<i32> {
+    if !interaction.interactive {
+        return Err("the terminal UI needs a terminal, use sync instead".into());
+    }
+
+    let win_keys = windows::get_reged_bt_devices(reg_export, interaction)?;
+    let linux_keys = BluezDir {
+        root: root.to_path_buf(),
+        interaction,
+    }
+    .read()?;
+    let mut rows = device_rows(root, &win_keys, &linux_keys)?;
+
+    let mut terminal = ratatui::init();
+    let applied = browse(&mut terminal, &mut rows, reg_export.is_none());
+    ratatui::restore();
+    if !applied.map_err(|e| e.into())? {
+        return Ok(report::EXIT_NOTHING_TO_DO);
+    }
+
+    // Applying is the confirmation and the direction decides conflicts, the partition
+    // is the one already chosen
+    let interaction = Interaction {
+        partition: Some(win_keys.source.clone()).filter(|_| reg_export.is_none()),
+        interactive: interaction.interactive,
+        assume_yes: true,
+    };
+    let policy = Policy {
+        conflict: ConflictPolicy::PreferSource,
+        ..Default::default()
+    };
+    // The preview shows device keys only, so local IRKs of the adapters stay as they are
+    let picked =
+        |keys: &SourceKeys, plan: Plan, device: fn(&DeviceRow) -> Option<UniBtDevice>| SourceKeys {
+            devices: rows
+                .iter()
+                .filter(|r| r.plan == plan)
+                .filter_map(device)
+                .collect(),
+            adapters: vec![],
+            ..keys.clone()
+        };
+
+    let mut report = report::SyncReport::default();
+    let mut to_linux = picked(&win_keys, Plan::ToLinux, |r| r.windows.clone());
+    if !to_linux.devices.is_empty() {
+        let mut sinks: Vec<Box<dyn KeySink>> = vec![Box::new(BluezDir {
+            root: root.to_path_buf(),
+            interaction: &interaction,
+        })];
+        let linux_report = pipeline::sync(&mut to_linux, &mut sinks, &policy, hooks)?;
+        report.roots.extend(linux_report.roots);
+        report.errors.extend(linux_report.errors);
+    }
+
+    let mut to_windows = picked(&linux_keys, Plan::ToWindows, |r| {
+        r.linux.as_ref().map(|(_, d)| d.clone())
+    });
+    if !to_windows.devices.is_empty() {
+        let mut sinks: Vec<Box<dyn KeySink>> = vec![Box::new(WindowsRegistry {
+            reg_export: reg_export.map(PathBuf::from),
+            interaction: &interaction,
+            root: root.to_path_buf(),
+        })];
+        let win_report = pipeline::sync(&mut to_windows, &mut sinks, &policy, hooks)?;
+        report.roots.extend(win_report.roots);
+        report.errors.extend(win_report.errors);
+    }
+
+    report.print_table();
+    Ok(report.exit_code())
+}
+
+/// Devices of both systems matched by adapter and address, sorted by them
+fn device_rows(
+    root: &Path,
+    win_keys: &SourceKeys,
+    linux_keys: &SourceKeys,
+) -> CustomResult<Vec<DeviceRow>> {
+    let names = NameIndex::load(root);
+    let same = |a: &UniBtDevice, b: &UniBtDevice| {
+        a.parent_address.0 == b.parent_address.0 && a.address.0 == b.address.0
+    };
+    let info = |d: &UniBtDevice| -> CustomResult<linux_bt_device::BtDevice> {
+        let info_path = root
+            .join(LINUX_BT_DIR)
+            .join(linux_bt_device::BtAddress::from(d.parent_address.clone()).0)
+            .join(linux_bt_device::BtAddress::from(d.address.clone()).0)
+            .join("info");
+        let info_str = std::fs::read_to_string(info_path).map_err(|e| e.into())?;
+        serde_ini::from_str(&info_str).map_err(|e| e.into())
+    };
+
+    let mut rows = vec![];
+    for win_dev in win_keys.devices.iter() {
+        let linux = match linux_keys.devices.iter().find(|d| same(d, win_dev)) {
+            Some(linux_dev) => Some((info(linux_dev)?, linux_dev.clone())),
+            None => None,
+        };
+        rows.push(DeviceRow {
+            adapter: linux_bt_device::BtAddress::from(win_dev.parent_address.clone()).0,
+            address: linux_bt_device::BtAddress::from(win_dev.address.clone()).0,
+            label: names.label(win_dev),
+            windows: Some(win_dev.clone()),
+            linux,
+            plan: Plan::Skip,
+        });
+    }
+
+    for linux_dev in linux_keys
+        .devices
+        .iter()
+        .filter(|d| !win_keys.devices.iter().any(|w| same(w, d)))
+    {
+        rows.push(DeviceRow {
+            adapter: linux_bt_device::BtAddress::from(linux_dev.parent_address.clone()).0,
+            address: linux_bt_device::BtAddress::from(linux_dev.address.clone()).0,
+            label: names.label(linux_dev),
+            windows: None,
+            linux: Some((info(linux_dev)?, linux_dev.clone())),
+            plan: Plan::Skip,
+        });
+    }
+
+    rows.sort_by(|a, b| (&a.adapter, &a.address).cmp(&(&b.adapter, &b.address)));
+    Ok(rows)
+}
+
+/// Event loop of the full-screen view, returns whether the user chose to apply.
+/// `windows_writable` is false for a saved export
+fn browse(
+    terminal: &mut DefaultTerminal,
+    rows: &mut [DeviceRow],
+    windows_writable: bool,
+) -> std::io::Result<bool> {
+    let mut state = TableState::default().with_selected(Some(0));
+
+    loop {
+        terminal.draw(|frame| draw(frame, rows, &mut state))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('a') => return Ok(true),
+            KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char(' ') => {
+                if let Some(row) = state.selected().and_then(|i| rows.get_mut(i)) {
+                    row.plan = row.next_plan(windows_writable);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, rows: &[DeviceRow], state: &mut TableState) {
+    let [table_area, diff_area, help_area] = Layout::vertical([
+        Constraint::Min(5),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let table = Table::new(
+        rows.iter().map(|r| {
+            Row::new(vec![
+                r.adapter.clone(),
+                r.label.clone(),
+                r.kind().to_string(),
+                r.key_status().to_string(),
+                r.last_seen(),
+                r.plan.to_string(),
+            ])
+        }),
+        [
+            Constraint::Length(17),
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Length(12),
+            Constraint::Length(20),
+            Constraint::Length(16),
+        ],
+    )
+    .header(
+        Row::new(vec![
+            "Adapter",
+            "Device",
+            "Type",
+            "Keys",
+            "Last seen",
+            "Sync",
+        ])
+        .bold(),
+    )
+    .row_highlight_style(Style::new().reversed())
+    .block(Block::bordered().title("bt-dualboot"));
+    frame.render_stateful_widget(table, table_area, state);
+
+    let diff: Vec<Line> = match state.selected().and_then(|i| rows.get(i)) {
+        Some(row) => row
+            .preview()
+            .iter()
+            .map(|c| {
+                Line::from(format!(
+                    "{} {} -> {}",
+                    c.section,
+                    c.before.as_deref().unwrap_or("none"),
+                    c.after.as_deref().unwrap_or("none")
+                ))
+            })
+            .collect(),
+        None => vec![],
+    };
+    frame.render_widget(
+        Paragraph::new(diff).block(Block::bordered().title("Changes (key fingerprints)")),
+        diff_area,
+    );
+
+    frame.render_widget(
+        Line::from("↑/↓ select  →/space change direction  a apply  q quit").dim(),
+        help_area,
+    );
+}
+
+impl DeviceRow {
+    /// Directions a device can be synced in, keys only go to a system the device is
+    /// already paired in
+    fn next_plan(&self, windows_writable: bool) -> Plan {
+        if self.windows.is_none() || self.linux.is_none() {
+            return Plan::Skip;
+        }
+
+        match self.plan {
+            Plan::Skip => Plan::ToLinux,
+            Plan::ToLinux if windows_writable => Plan::ToWindows,
+            Plan::ToLinux | Plan::ToWindows => Plan::Skip,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        let device = self
+            .windows
+            .as_ref()
+            .or(self.linux.as_ref().map(|(_, d)| d));
+        match device.map(|d| (d.link_key.is_some(), d.ltk.is_some())) {
+            Some((true, true)) => "dual",
+            Some((false, true)) => "LE",
+            _ => "classic",
+        }
+    }
+
+    fn key_status(&self) -> &'static str {
+        match (self.windows.as_ref(), self.linux.as_ref()) {
+            (Some(win_dev), Some((linux_dev, _))) => {
+                match status::compare_devices(linux_dev, win_dev) {
+                    DeviceStatus::InSync => "in sync",
+                    _ => "keys differ",
+                }
+            }
+            (Some(_), None) => "only windows",
+            _ => "only linux",
+        }
+    }
+
+    fn last_seen(&self) -> String {
+        self.windows
+            .as_ref()
+            .and_then(|d| d.meta.as_ref())
+            .and_then(|m| m.last_connected.or(m.last_seen))
+            .map(format_time)
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// Key sections the plan would change, as fingerprints, nothing for a skipped device
+    fn preview(&self) -> Vec<SectionChange> {
+        let (Some(win_dev), Some((linux_dev, linux_uni))) = (&self.windows, &self.linux) else {
+            return vec![];
+        };
+
+        match self.plan {
+            Plan::Skip => vec![],
+            Plan::ToLinux => status::changes_of_sync(linux_dev, win_dev),
+            Plan::ToWindows => {
+                ledger::changed_keys(win_dev, &windows::merged_keys(win_dev, linux_uni))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Plan::Skip => write!(f, "skip"),
+            Plan::ToLinux => write!(f, "windows -> linux"),
+            Plan::ToWindows => write!(f, "linux -> windows"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use super::*;
+    use crate::bt_device::uni_bt_device::AddressType;
+
+    /// Device of the dual-mode fixture, paired in windows and with `info` in linux
+    fn row(info: &str) -> DeviceRow {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dual-mode");
+        let interaction = Interaction {
+            partition: None,
+            interactive: false,
+            assume_yes: false,
+        };
+        let win_dev =
+            windows::get_reged_bt_devices(Some(&fixture.join("export.reg")), &interaction)
+                .expect("readable export")
+                .devices
+                .into_iter()
+                .find(|d| {
+                    linux_bt_device::BtAddress::from(d.address.clone()).0 == "A0:E9:DB:0C:2B:4E"
+                })
+                .expect("device in the export");
+        let linux_dev: linux_bt_device::BtDevice =
+            serde_ini::from_str(info).expect("parsable info");
+        // the keys of the info file, as `BluezDir::read` gets them
+        let parent_address = linux_bt_device::BtAddress("C0:FB:F9:60:1C:13".to_string())
+            .to_uni(AddressType::Public)
+            .expect("adapter address");
+        let address = linux_bt_device::BtAddress("A0:E9:DB:0C:2B:4E".to_string())
+            .to_uni(linux_dev.address_type())
+            .expect("device address");
+        let linux_uni = linux_dev.uni_device(parent_address, address);
+
+        DeviceRow {
+            adapter: "C0:FB:F9:60:1C:13".to_string(),
+            address: "A0:E9:DB:0C:2B:4E".to_string(),
+            label: "A0:E9:DB:0C:2B:4E".to_string(),
+            windows: Some(win_dev.clone()),
+            linux: Some((linux_dev, linux_uni)),
+            plan: Plan::Skip,
+        }
+    }
+
+    fn synced_info() -> String {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dual-mode");
+        read_to_string(fixture.join("expected/C0:FB:F9:60:1C:13/A0:E9:DB:0C:2B:4E/info"))
+            .expect("readable info file")
+    }
+
+    #[test]
+    fn cycles_through_directions() {
+        let mut row = row(&synced_info());
+        let mut plans = vec![];
+        for _ in 0..3 {
+            row.plan = row.next_plan(true);
+            plans.push(row.plan);
+        }
+        assert!(plans == [Plan::ToLinux, Plan::ToWindows, Plan::Skip]);
+
+        row.plan = Plan::ToLinux;
+        assert!(
+            row.next_plan(false) == Plan::Skip,
+            "saved export can't be written"
+        );
+
+        row.linux = None;
+        assert!(row.next_plan(true) == Plan::Skip, "only paired in windows");
+    }
+
+    #[test]
+    fn previews_changes_of_the_plan() {
+        let info = synced_info()
+            .replace(
+                "9402CC8C3DF58B32D6ED2FF0267A16E7",
+                "11111111111111111111111111111111",
+            )
+            .replace(
+                "6122580C022537A159910EE5B5C76116",
+                "22222222222222222222222222222222",
+            );
+        let mut changed = row(&info);
+        changed.plan = Plan::ToWindows;
+        let sections: Vec<_> = changed.preview().into_iter().map(|c| c.section).collect();
+        assert_eq!(sections, ["LinkKey", "LongTermKey"]);
+
+        // a classic windows pairing only gets the link key, like the sink writes it
+        if let Some(win_dev) = changed.windows.as_mut() {
+            win_dev.ltk = None;
+        }
+        let sections: Vec<_> = changed.preview().into_iter().map(|c| c.section).collect();
+        assert_eq!(sections, ["LinkKey"]);
+    }
+}
@@ -1,3 +1,38 @@
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
 pub fn is_valid_64_hex(input: &str) -> bool {
 	input.len() == 12 && input.chars().all(|c| c.is_ascii_hexdigit())
-}
\ No newline at end of file
+}
+
+/// Short fingerprint of key material, safe to print instead of the key itself
+///
+/// "786DC4332D385A48C4E718FE0B84FF20" -> "15ce4746"
+pub fn fingerprint(key: &str) -> String {
+	Sha256::digest(key.as_bytes())
+		.iter()
+		.take(4)
+		.map(|b| format!("{:02x}", b))
+		.collect()
+}
+
+pub fn is_valid_linux_address(input: &str) -> bool {
+	input.len() == 17
+		&& input
+			.split(':')
+			.all(|b| b.len() == 2 && b.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// SystemTime -> "2023-11-25T21:01:32Z"
+pub fn format_time(time: SystemTime) -> String {
+	OffsetDateTime::from(time)
+		.format(&Rfc3339)
+		.unwrap_or_else(|_| "?".to_string())
+}
+
+/// "2023-11-25T21:01:32Z" -> SystemTime
+pub fn parse_time(time: &str) -> Option<SystemTime> {
+	OffsetDateTime::parse(time, &Rfc3339).ok().map(SystemTime::from)
+}
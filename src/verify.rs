@@ -0,0 +1,188 @@
+use std::{
+    fs::{metadata, read_to_string},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use log::error;
+
+use crate::{
+    bt_device::{linux_bt_device, uni_bt_device::UniBtDevice},
+    ledger::{self, Direction, SectionChange},
+    names::NameIndex,
+    pipeline::System,
+    status::changes_of_sync,
+    windows::get_reged_bt_devices,
+    CustomResult, Interaction, LINUX_BT_DIR,
+};
+
+pub const EXIT_VERIFIED: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_DRIFT: i32 = 2;
+
+/// bluetoothd creates adapter and device dirs with 0700 and info files with 0600
+const DIR_MODE: u32 = 0o700;
+const INFO_MODE: u32 = 0o600;
+
+/// Way an info file differs from what a sync from windows writes
+pub enum Drift {
+    /// bluez would drop the whole file
+    Unparsable(String),
+    /// Synced device which is no longer paired in linux, or in windows
+    Unpaired { in_linux: bool },
+    /// Key section, or `General.AddressType`, a sync would change, `before` is linux
+    Section(SectionChange),
+    /// Mode of the info file or of a dir above it, e.g. 0o644 instead of 0o600
+    Mode {
+        path: String,
+        mode: u32,
+        expected: u32,
+    },
+}
+
+pub struct DeviceReport {
+    pub adapter: String,
+    /// "WH-1000XM4 (4C:87:5D:26:DC:9F)" or just the address when the name is unknown
+    pub label: String,
+    pub drift: Vec<Drift>,
+}
+
+/// Prints drift of every device changed by the last sync from windows and returns the
+/// exit code
+pub fn run(root: &Path, reg_export: Option<&Path>, interaction: &Interaction) -> i32 {
+    let reports = match get_drift(root, reg_export, interaction) {
+        Ok(reports) => reports,
+        Err(e) => {
+            error!("can't verify {:?}: {:?}", root, e);
+            return EXIT_ERROR;
+        }
+    };
+
+    for report in reports.iter() {
+        if report.drift.is_empty() {
+            println!("{} {} verified", report.adapter, report.label);
+        } else {
+            let drift: Vec<_> = report.drift.iter().map(|d| format!("[{}]", d)).collect();
+            println!(
+                "{} {} drift {}",
+                report.adapter,
+                report.label,
+                drift.join(" ")
+            );
+        }
+    }
+
+    if reports.iter().all(|r| r.drift.is_empty()) {
+        EXIT_VERIFIED
+    } else {
+        EXIT_DRIFT
+    }
+}
+
+/// Re-reads the info files which the last sync from windows recorded in the ledger of
+/// the linux installation at `root`, changes of the adapter's own IRK aren't checked
+pub fn get_drift(
+    root: &Path,
+    reg_export: Option<&Path>,
+    interaction: &Interaction,
+) -> CustomResult<Vec<DeviceReport>> {
+    let from_windows = Direction {
+        from: System::Windows,
+        to: System::Linux,
+    };
+    let entries: Vec<_> = ledger::read(root)?
+        .into_iter()
+        .filter(|e| e.direction == from_windows && e.device != e.adapter)
+        .collect();
+    let Some(last_sync) = entries.iter().map(|e| e.time.clone()).max() else {
+        return Err(format!("no sync from windows in the ledger of {:?}", root).into());
+    };
+
+    let win_devices = get_reged_bt_devices(reg_export, interaction)?.devices;
+    let names = NameIndex::load(root);
+
+    entries
+        .iter()
+        .filter(|e| e.time == last_sync)
+        .map(|entry| {
+            let adapter_dir = root.join(LINUX_BT_DIR).join(&entry.adapter);
+            let device_dir = adapter_dir.join(&entry.device);
+            let win_dev = win_devices.iter().find(|d| {
+                linux_bt_device::BtAddress::from(d.parent_address.clone()).0 == entry.adapter
+                    && linux_bt_device::BtAddress::from(d.address.clone()).0 == entry.device
+            });
+
+            let drift = match win_dev {
+                _ if !device_dir.join("info").exists() => vec![Drift::Unpaired { in_linux: true }],
+                None => vec![Drift::Unpaired { in_linux: false }],
+                Some(win_dev) => {
+                    let mut drift = check_modes(&adapter_dir, &device_dir)?;
+                    drift.extend(check_info(&device_dir.join("info"), win_dev)?);
+                    drift
+                }
+            };
+
+            Ok(DeviceReport {
+                label: names.address_label(&entry.adapter, &entry.device),
+                adapter: entry.adapter.clone(),
+                drift,
+            })
+        })
+        .collect()
+}
+
+fn check_info(info_path: &Path, win_dev: &UniBtDevice) -> CustomResult<Vec<Drift>> {
+    let info_str = read_to_string(info_path).map_err(|e| e.into())?;
+    let linux_dev: linux_bt_device::BtDevice = match serde_ini::from_str(&info_str) {
+        Ok(linux_dev) => linux_dev,
+        Err(e) => return Ok(vec![Drift::Unparsable(format!("{:?}", e))]),
+    };
+
+    Ok(changes_of_sync(&linux_dev, win_dev)
+        .into_iter()
+        .map(Drift::Section)
+        .collect())
+}
+
+fn check_modes(adapter_dir: &Path, device_dir: &Path) -> CustomResult<Vec<Drift>> {
+    let mut drift = vec![];
+
+    for (path, expected) in [
+        (adapter_dir.to_path_buf(), DIR_MODE),
+        (device_dir.to_path_buf(), DIR_MODE),
+        (device_dir.join("info"), INFO_MODE),
+    ] {
+        let mode = metadata(&path).map_err(|e| e.into())?.permissions().mode() & 0o777;
+        if mode != expected {
+            drift.push(Drift::Mode {
+                path: path.display().to_string(),
+                mode,
+                expected,
+            });
+        }
+    }
+
+    Ok(drift)
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::Unparsable(error) => write!(f, "info doesn't parse: {}", error),
+            Drift::Unpaired { in_linux: true } => write!(f, "no longer paired in linux"),
+            Drift::Unpaired { in_linux: false } => write!(f, "no longer paired in windows"),
+            Drift::Section(change) => write!(
+                f,
+                "{}: linux {} windows {}",
+                change.section,
+                change.before.as_deref().unwrap_or("missing"),
+                change.after.as_deref().unwrap_or("missing")
+            ),
+            Drift::Mode {
+                path,
+                mode,
+                expected,
+            } => write!(f, "{} has mode {:o}, expected {:o}", path, mode, expected),
+        }
+    }
+}
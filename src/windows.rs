@@ -0,0 +1,683 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error, info, warn};
+
+use crate::{
+    bt_device::{
+        linux_bt_device,
+        uni_bt_device::{self, UniBtDevice},
+        win_bt_device,
+    },
+    choose_mount, confirm_list, freshness, hive,
+    ledger::{self, Direction},
+    names::NameIndex,
+    ntfs,
+    pipeline::{self, ConflictPolicy, KeySink, KeySource, Policy, SourceKeys, System},
+    privilege::{self, Access},
+    report,
+    utils::{format_time, is_valid_64_hex, parse_time},
+    CustomResult, Interaction, LINUX_BACKUP_DIR, REG_KEY_BLUETOOTH_DEVICES,
+    REG_KEY_BLUETOOTH_PAIRING_KEYS, WINDOWS10_REGISTRY_PATH,
+};
+
+/// Registry of a mounted windows partition, or a saved export of it which can only
+/// be read
+pub struct WindowsRegistry<'a> {
+    pub reg_export: Option<PathBuf>,
+    pub interaction: &'a Interaction,
+    /// Linux installation whose device names, backup dir and ledger are used
+    pub root: PathBuf,
+}
+
+impl KeySource for WindowsRegistry<'_> {
+    fn read(&mut self) -> CustomResult<SourceKeys> {
+        get_reged_bt_devices(self.reg_export.as_deref(), self.interaction)
+    }
+}
+
+impl KeySink for WindowsRegistry<'_> {
+    fn system(&self) -> System {
+        System::Windows
+    }
+
+    fn target(&self) -> String {
+        match (
+            self.reg_export.as_ref(),
+            self.interaction.partition.as_ref(),
+        ) {
+            (Some(reg_export), _) => reg_export.display().to_string(),
+            (None, Some(partition)) => partition.clone(),
+            (None, None) => "the windows partition".to_string(),
+        }
+    }
+
+    /// Writes keys of devices paired in both systems into the registry, the policy
+    /// decides devices whose keys differ. The hive is written once for all devices,
+    /// its backup is kept in the backup dir of the root
+    fn write(&mut self, keys: &SourceKeys, policy: &Policy) -> CustomResult<report::RootReport> {
+        if self.reg_export.is_some() {
+            return Err(
+                "keys can't be written into a saved export, mount the windows partition instead"
+                    .into(),
+            );
+        }
+
+        let SourceKeys {
+            source: win_mount,
+            devices: win_devices,
+            ..
+        } = get_reged_bt_devices(None, self.interaction)?;
+        let names = NameIndex::load(&self.root);
+        let now = SystemTime::now();
+        let ledger = ledger::read(&self.root).unwrap_or_else(|e| {
+            warn!("can't read the ledger of {:?}: {:?}", self.root, e);
+            vec![]
+        });
+
+        let mut devices = vec![];
+        let mut changed = vec![];
+        for win_dev in win_devices.iter() {
+            let (report, update) = self.plan_device(win_dev, keys, policy, &ledger, &names)?;
+            if let Some((updated, sections)) = update {
+                changed.push((devices.len(), updated, sections));
+            }
+            devices.push(report);
+        }
+
+        let mut win_report = report::RootReport {
+            root: PathBuf::from(&win_mount),
+            backup_dir: None,
+            identities: vec![],
+            devices,
+        };
+        if changed.is_empty() {
+            return Ok(win_report);
+        }
+
+        let labels: Vec<_> = changed.iter().map(|(_, d, _)| names.label(d)).collect();
+        let question = format!(
+            "write {} device(s) from {} into the windows registry on {}?",
+            changed.len(),
+            keys.source,
+            win_mount
+        );
+        let outcome = if !confirm_list(&question, &labels, self.interaction)? {
+            warn!("skipped {:?}", win_mount);
+            Some(report::Outcome::Skipped {
+                reason: "not confirmed".to_string(),
+            })
+        } else {
+            let started = now
+                .duration_since(UNIX_EPOCH)
+                .expect("clock is after 1970")
+                .as_secs();
+            let backup_dir = self
+                .root
+                .join(LINUX_BACKUP_DIR)
+                .join(started.to_string())
+                .join("windows");
+            let updated: Vec<_> = changed.iter().map(|(_, d, _)| d.clone()).collect();
+            let reg = win_bt_device::reg_file(
+                &format!(
+                    r"HKEY_LOCAL_MACHINE\SYSTEM\{}",
+                    REG_KEY_BLUETOOTH_PAIRING_KEYS
+                ),
+                &updated,
+            );
+
+            match hive::import(
+                Path::new(&win_mount),
+                &Path::new(&win_mount).join(WINDOWS10_REGISTRY_PATH),
+                &reg,
+                &backup_dir,
+            ) {
+                Ok(()) => {
+                    win_report.backup_dir = Some(backup_dir);
+                    None
+                }
+                Err(e) => {
+                    error!(
+                        "can't update the windows registry on {}: {:?}",
+                        win_mount, e
+                    );
+                    Some(report::Outcome::Failed {
+                        error: format!("{:?}", e),
+                    })
+                }
+            }
+        };
+
+        let mut ledger_entries = vec![];
+        for (i, device, sections) in changed {
+            match outcome.as_ref() {
+                Some(outcome) => win_report.devices[i].outcome = outcome.clone(),
+                None => {
+                    let adapter = win_report.devices[i].adapter.clone();
+                    let address = win_report.devices[i].address.clone();
+                    info!(
+                        device = address.as_str(), adapter = adapter.as_str(), action = "updated";
+                        "updated {} in the windows registry", names.label(&device)
+                    );
+                    ledger_entries.push(ledger::LedgerEntry {
+                        time: format_time(now),
+                        direction: Direction {
+                            from: keys.system,
+                            to: System::Windows,
+                        },
+                        source: keys.source.clone(),
+                        adapter,
+                        device: address,
+                        name: names.device_name(&device),
+                        sections,
+                    })
+                }
+            }
+        }
+        if let Err(e) = ledger::append(&self.root, &ledger_entries) {
+            warn!(
+                "can't record changes in the ledger of {:?}: {:?}",
+                self.root, e
+            );
+        }
+
+        Ok(win_report)
+    }
+}
+
+/// Keys to write into windows and how they change
+type KeyUpdate = (UniBtDevice, Vec<ledger::SectionChange>);
+
+impl WindowsRegistry<'_> {
+    /// What a sync does with `win_dev`, with the keys to write and their changes when
+    /// they differ from the source ones and `policy` lets them be replaced
+    fn plan_device(
+        &self,
+        win_dev: &UniBtDevice,
+        keys: &SourceKeys,
+        policy: &Policy,
+        ledger: &[ledger::LedgerEntry],
+        names: &NameIndex,
+    ) -> CustomResult<(report::DeviceReport, Option<KeyUpdate>)> {
+        let adapter = linux_bt_device::BtAddress::from(win_dev.parent_address.clone()).0;
+        let address = linux_bt_device::BtAddress::from(win_dev.address.clone()).0;
+        let report = |outcome, policy| report::DeviceReport {
+            adapter: adapter.clone(),
+            address: address.clone(),
+            name: names.device_name(win_dev),
+            outcome,
+            policy,
+        };
+
+        let source_dev = keys.devices.iter().find(|d| {
+            d.parent_address.0 == win_dev.parent_address.0 && d.address.0 == win_dev.address.0
+        });
+        let Some(source_dev) = source_dev else {
+            warn!(
+                "device {} from windows is not paired in {} {}",
+                names.label(win_dev),
+                keys.system,
+                keys.source
+            );
+            let reason = format!("not paired in {}", keys.system);
+            return Ok((report(report::Outcome::Skipped { reason }, None), None));
+        };
+
+        let updated = merged_keys(win_dev, source_dev);
+
+        let sections = ledger::changed_keys(win_dev, &updated);
+        if sections.is_empty() {
+            debug!("{} is already up to date in windows", names.label(win_dev));
+            return Ok((report(report::Outcome::Unchanged, None), None));
+        }
+
+        let conflict = policy.conflict_for(&address);
+        let overwrite = conflict != ConflictPolicy::Skip && {
+            // only info files of a linux source tell when it was last used
+            let last_sync = ledger
+                .iter()
+                .filter(|e| e.adapter == adapter && e.device == address)
+                .filter_map(|e| parse_time(&e.time))
+                .max();
+            let freshness = (keys.system == System::Linux)
+                .then(|| freshness::compare(win_dev, &self.root, &adapter, &address, last_sync))
+                .flatten();
+            let direction = Direction {
+                from: keys.system,
+                to: System::Windows,
+            };
+            pipeline::resolve_conflict(
+                &names.label(win_dev),
+                conflict,
+                freshness,
+                direction,
+                self.interaction,
+            )?
+        };
+        if !overwrite {
+            info!(
+                device = address.as_str(), adapter = adapter.as_str(), action = "kept";
+                "kept the windows pairing of {} because of {}", names.label(win_dev), conflict
+            );
+            let reason = pipeline::kept_reason(conflict, System::Windows);
+            return Ok((
+                report(report::Outcome::Skipped { reason }, Some(conflict)),
+                None,
+            ));
+        }
+
+        Ok((
+            report(report::Outcome::Updated, Some(conflict)),
+            Some((updated, sections)),
+        ))
+    }
+}
+
+/// Keys of `source_dev` as they're written over the windows pairing `win_dev`
+pub fn merged_keys(win_dev: &UniBtDevice, source_dev: &UniBtDevice) -> UniBtDevice {
+    // Only kinds of keys windows already has, a classic pairing doesn't become LE
+    let source_dev = source_dev.clone();
+    let le = win_dev.ltk.is_some();
+    UniBtDevice {
+        link_key: source_dev
+            .link_key
+            .filter(|_| win_dev.link_key.is_some())
+            .or(win_dev.link_key.clone()),
+        ltk: source_dev.ltk.filter(|_| le).or(win_dev.ltk.clone()),
+        e_rand: source_dev.e_rand.filter(|_| le).or(win_dev.e_rand.clone()),
+        e_div: source_dev.e_div.filter(|_| le).or(win_dev.e_div.clone()),
+        irk: source_dev.irk.filter(|_| le).or(win_dev.irk.clone()),
+        csrk: source_dev.csrk.filter(|_| le).or(win_dev.csrk.clone()),
+        ..win_dev.clone()
+    }
+}
+
+/// Devices paired in windows and where they were read from, the mount point
+/// of the windows partition or the path of a saved export
+pub fn get_reged_bt_devices(
+    reg_export: Option<&Path>,
+    interaction: &Interaction,
+) -> CustomResult<SourceKeys> {
+    let (source, raw_values) = if let Some(reg_export) = reg_export {
+        debug!("reading saved registry export {:?}", reg_export);
+        let output = read_to_string(reg_export).map_err(|e| e.into())?;
+        (
+            reg_export.display().to_string(),
+            parse_chntpw_export(&output)?,
+        )
+    } else {
+        let win_mounts = get_windows_mounts()?;
+        let encrypted = ntfs::bitlocker_partitions();
+        if !encrypted.is_empty() {
+            if win_mounts.is_empty() {
+                return Err(format!(
+                    "no windows partitions, {}",
+                    ntfs::bitlocker_hint(&encrypted)
+                )
+                .into());
+            }
+            warn!(
+                "keys of another windows installation may be on {}",
+                ntfs::bitlocker_hint(&encrypted)
+            );
+        }
+        let win_mount = choose_mount(win_mounts, "windows", interaction)?;
+        warn_unclean_windows(Path::new(&win_mount));
+        let hive_path = Path::new(&win_mount).join(WINDOWS10_REGISTRY_PATH);
+        privilege::check(&[(hive_path.clone(), Access::Read)])?;
+        let hive = hive::open(&hive_path)?;
+        let output = get_chntpw_export(hive.path(), REG_KEY_BLUETOOTH_PAIRING_KEYS)?;
+        let mut raw_values = parse_chntpw_export(&output)?;
+
+        match get_chntpw_export(hive.path(), REG_KEY_BLUETOOTH_DEVICES)
+            .and_then(|output| parse_chntpw_export(&output))
+        {
+            Ok(devices_values) => raw_values.extend(devices_values),
+            Err(e) => warn!("can't read devices metadata: {:?}", e),
+        }
+        (win_mount, raw_values)
+    };
+
+    // Keys and Devices are siblings
+    // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys
+    // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Devices
+    let (raw_values, devices_values): (HashMap<_, _>, HashMap<_, _>) = raw_values
+        .into_iter()
+        .partition(|(k, _)| k.split('\\').nth(6) == Some("Keys"));
+
+    let bt_values_1: Vec<_> = raw_values
+        .iter()
+        .filter(|(k, _)| {
+            // Match bt adapters
+            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys\\c0fbf9601c13
+            let path_len = k.split("\\").count();
+            path_len == 8
+        })
+        .map(|(p_k, h)| {
+            // Remove "" quotes around the keys
+            // "AuthReq" -> AuthReq
+            let n_h: HashMap<_, _> = h
+                .into_iter()
+                .map(|(k, v)| (k.trim_matches('"').to_string(), v))
+                .collect();
+            (p_k, n_h)
+        })
+        .flat_map(|(p_k, h)| {
+            let parent_address = p_k
+                .split('\\')
+                .collect::<Vec<&str>>()
+                .into_iter()
+                .rev()
+                .next()
+                .expect("checked by path_len")
+                .to_string();
+
+            h.into_iter()
+                .filter(|(k, _)| is_valid_64_hex(k))
+                .map(|(k, v)| {
+                    win_bt_device::BtDeviceBuilder::new()
+                        .address(k)
+                        .link_key(v.clone())
+                        .parent_address(parent_address.clone())
+                        .build()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    debug!("found {} device(s)", bt_values_1.len());
+
+    let adapters: Vec<_> = raw_values
+        .iter()
+        .filter(|(k, _)| k.split('\\').count() == 8)
+        .map(|(k, v)| {
+            let n_h: HashMap<_, _> = v
+                .iter()
+                .map(|(n_k, n_v)| (n_k.trim_matches('"').to_string(), n_v.clone()))
+                .collect();
+            let address = k.rsplit('\\').next().expect("always has a last part");
+            win_bt_device::adapter(address.to_string(), &n_h)
+        })
+        .collect();
+
+    let bt_values_2: Vec<_> = raw_values
+        .into_iter()
+        .filter(|(k, _)| {
+            // Ignore an empty set and bt adapters
+            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys
+            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Keys\\c0fbf9601c13
+            let path_len = k.split("\\").count();
+            path_len > 8
+        })
+        .map(|(k, v)| {
+            // Remove "" quotes around the keys
+            // "AuthReq" -> AuthReq
+            let n_h: HashMap<_, _> = v
+                .into_iter()
+                .map(|(n_k, n_v)| (n_k.trim_matches('"').to_string(), n_v))
+                .collect();
+            (k, n_h)
+        })
+        .map(|(k, v)| {
+            let parent_address = k
+                .split("\\")
+                .collect::<Vec<&str>>()
+                .into_iter()
+                .rev()
+                .skip(1)
+                .next()
+                .expect("should have adapter's mac")
+                .to_string();
+
+            win_bt_device::BtDeviceBuilder::new()
+                .entries51(v)
+                .parent_address(parent_address)
+                .build()
+        })
+        .collect();
+    debug!("found {} separate device(s)", bt_values_2.len());
+
+    let mut all_devices = vec![];
+    all_devices.extend(bt_values_1);
+    all_devices.extend(bt_values_2);
+    let mut all_devices = uni_bt_device::merge_dual_mode(all_devices);
+
+    let devices_meta = get_reged_devices_meta(devices_values);
+    for device in all_devices.iter_mut() {
+        device.meta = devices_meta
+            .iter()
+            .find(|(address, _)| address.0 == device.address.0)
+            .map(|(_, meta)| meta.clone());
+    }
+
+    Ok(SourceKeys {
+        source,
+        system: System::Windows,
+        devices: all_devices,
+        adapters,
+    })
+}
+
+/// Names and other descriptive values windows keeps about every device it has seen
+fn get_reged_devices_meta(
+    raw_values: HashMap<String, HashMap<String, String>>,
+) -> Vec<(uni_bt_device::Address, uni_bt_device::DeviceMeta)> {
+    let devices_meta: Vec<_> = raw_values
+        .into_iter()
+        .filter(|(k, _)| {
+            // Match only devices, not their cached services
+            // HKEY_LOCAL_MACHINE\\SYSTEM\\ControlSet001\\Services\\BTHPORT\\Parameters\\Devices\\4c875d26dc9f
+            let path_len = k.split('\\').count();
+            let address = k.rsplit('\\').next().expect("always has a last part");
+            path_len == 8 && is_valid_64_hex(address)
+        })
+        .filter_map(|(k, v)| {
+            // Remove "" quotes around the keys
+            // "Name" -> Name
+            let n_h: HashMap<_, _> = v
+                .into_iter()
+                .map(|(n_k, n_v)| (n_k.trim_matches('"').to_string(), n_v))
+                .collect();
+            let address = k.rsplit('\\').next().expect("checked by path_len");
+            match win_bt_device::device_meta(address.to_string(), n_h) {
+                Ok(meta) => Some(meta),
+                Err(e) => {
+                    warn!("skipped metadata of device {}: {}", address, e);
+                    None
+                }
+            }
+        })
+        .collect();
+    debug!("found metadata of {} device(s)", devices_meta.len());
+
+    devices_meta
+}
+
+/// Turns `reged -E` output into `key path -> (value name -> value)`
+pub fn parse_chntpw_export(output: &str) -> CustomResult<HashMap<String, HashMap<String, String>>> {
+    let output_clean = output
+        .lines()
+        .skip(1)
+        .take_while(|l| !l.starts_with("reged version"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        // Long binary values are wrapped with a trailing backslash
+        // "Name"=hex:57,48,2d,31,30,30,30,58,4d,34,\
+        //   00
+        .replace("\\\n  ", "");
+    serde_ini::from_str(&output_clean).map_err(|e| e.into())
+}
+
+pub fn get_chntpw_export(win_reg: &Path, reg_key: &str) -> CustomResult<String> {
+    if !win_reg.exists() {
+        return Err(format!("didn't find registry {:?}", win_reg).into());
+    }
+
+    debug!(
+        "running chntpw on the {} registry",
+        win_reg.to_str().expect("checked existence")
+    );
+
+    let chntpw = Command::new("reged")
+        .args([
+            "-E",
+            "-x",
+            win_reg.to_str().expect("checked existence"),
+            r"HKEY_LOCAL_MACHINE\SYSTEM",
+            reg_key,
+            "/dev/stdout",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.into())?;
+
+    let output = chntpw.wait_with_output().map_err(|e| e.into())?.stdout;
+    let output_str = String::from_utf8(output).map_err(|e| e.into())?;
+    Ok(output_str)
+}
+
+/// Registry of a hibernated or dirty windows may not have the latest pairings yet
+fn warn_unclean_windows(win_mount: &Path) {
+    let state = ntfs::volume_state(win_mount);
+    if state.hibernated {
+        warn!(
+            "windows on {:?} is hibernated or was shut down with Fast Startup, its pairing keys may be stale. \
+            Disable Fast Startup or restart windows before shutting it down",
+            win_mount
+        );
+    } else if state.is_unclean() {
+        warn!(
+            "NTFS on {:?} was not cleanly unmounted, its pairing keys may be stale",
+            win_mount
+        );
+    }
+}
+
+fn get_windows_mounts() -> CustomResult<Vec<String>> {
+    let mounts = read_to_string("/proc/mounts").map_err(|e| e.into())?;
+
+    // Any source may hold windows, e.g. an unlocked dislocker-file mounted by ntfs-3g
+    // /dev/mapper/windows /mnt/windows ntfs3 ro,relatime 0 0
+    // /mnt/dislocker/dislocker-file /mnt/windows fuseblk ro,relatime 0 0
+    let mut win_mounts: Vec<_> = mounts
+        .split('\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let mnt_point = l
+                .split(' ')
+                .skip(1)
+                .next()
+                .expect(&format!("no mounting point in the dev entry {}", l))
+                .to_string();
+            mnt_point
+        })
+        .filter(|mnt_p| {
+            Path::new(mnt_p)
+                .join(Path::new(WINDOWS10_REGISTRY_PATH))
+                .exists()
+        })
+        .collect();
+    // Mounts stacked on the same mount point
+    win_mounts.sort();
+    win_mounts.dedup();
+
+    Ok(win_mounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir_all, write, File},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{bt_device::uni_bt_device::LinkKey, LINUX_BT_DIR};
+
+    #[test]
+    fn resolves_conflicts_with_linux_keys() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/classic");
+        let interaction = Interaction {
+            partition: None,
+            interactive: false,
+            assume_yes: true,
+        };
+        let win_dev = get_reged_bt_devices(Some(&fixture.join("export.reg")), &interaction)
+            .expect("readable export")
+            .devices
+            .into_iter()
+            .find(|d| linux_bt_device::BtAddress::from(d.address.clone()).0 == "4C:87:5D:26:DC:9F")
+            .expect("device in the export");
+
+        let root = tempfile::tempdir().expect("temp dir");
+        let info = root
+            .path()
+            .join(LINUX_BT_DIR)
+            .join("C0:FB:F9:60:1C:13/4C:87:5D:26:DC:9F/info");
+        create_dir_all(info.parent().expect("device dir")).expect("create device dir");
+        write(&info, "").expect("write info file");
+
+        let registry = WindowsRegistry {
+            reg_export: None,
+            interaction: &interaction,
+            root: root.path().to_path_buf(),
+        };
+        let keys = SourceKeys {
+            source: root.path().display().to_string(),
+            system: System::Linux,
+            devices: vec![UniBtDevice {
+                link_key: Some(LinkKey([0x11; 16])),
+                ..win_dev.clone()
+            }],
+            adapters: vec![],
+        };
+        let plan = |conflict, used_in_linux| {
+            File::options()
+                .write(true)
+                .open(&info)
+                .and_then(|f| f.set_modified(used_in_linux))
+                .expect("set modification time");
+            let policy = Policy {
+                conflict,
+                ..Default::default()
+            };
+            let names = NameIndex::load(root.path());
+            let (report, update) = registry
+                .plan_device(&win_dev, &keys, &policy, &[], &names)
+                .expect("planned device");
+            assert_eq!(report.policy, Some(conflict));
+            (report.outcome.to_string(), update)
+        };
+        let long_ago = UNIX_EPOCH + Duration::from_secs(1);
+
+        let (outcome, update) = plan(ConflictPolicy::PreferTarget, SystemTime::now());
+        assert_eq!(outcome, "skipped: windows keys are preferred");
+        assert!(update.is_none());
+
+        // connected in windows after linux last used it
+        let (outcome, update) = plan(ConflictPolicy::Newest, long_ago);
+        assert_eq!(
+            outcome,
+            "skipped: windows pairing is newer, use --force to overwrite"
+        );
+        assert!(update.is_none());
+
+        let (outcome, update) = plan(ConflictPolicy::Skip, SystemTime::now());
+        assert_eq!(
+            outcome,
+            "skipped: keys differ, left alone by the skip policy"
+        );
+        assert!(update.is_none());
+
+        let (outcome, update) = plan(ConflictPolicy::Newest, SystemTime::now());
+        assert_eq!(outcome, "updated");
+        let (updated, sections) = update.expect("keys to write");
+        assert_eq!(updated.link_key.map(|k| k.0), Some([0x11; 16]));
+        let sections: Vec<_> = sections.iter().map(|c| c.section.as_str()).collect();
+        assert_eq!(sections, ["LinkKey"]);
+    }
+}
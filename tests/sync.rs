@@ -0,0 +1,811 @@
+//! Runs the whole windows -> linux sync against fixture registry exports and fake
+//! `/var/lib/bluetooth` trees, then compares the resulting `info` files with golden ones.
+//!
+//! Every folder in `tests/fixtures` is a case:
+//! - `export.reg` is what `reged -x` prints for `ControlSet001\Services\BTHPORT\Parameters`
+//! - `args` holds extra arguments of `sync`, one per line, and is optional
+//! - `before` is `/var/lib/bluetooth` before the sync
+//! - `expected` is `/var/lib/bluetooth` after the sync
+//!
+//! `tests/fixtures/macos` isn't a case, it holds plist files for unit tests of the
+//! macos reader
+
+use std::{
+    collections::HashMap,
+    fs::{copy, create_dir_all, read_dir, read_to_string, File},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tempfile::TempDir;
+
+type Ini = HashMap<String, HashMap<String, String>>;
+
+const LINUX_BT_DIR: &str = "var/lib/bluetooth";
+const LINUX_BACKUP_DIR: &str = "var/lib/bt-dualboot/backups";
+const LINUX_LEDGER_PATH: &str = "var/lib/bt-dualboot/ledger.jsonl";
+/// 2020-09-13, older than `LastConnected` of every fixture device
+const PAIRED_IN_LINUX_AT: Duration = Duration::from_secs(1_600_000_000);
+
+fn fixture_dir(case: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(case)
+}
+
+/// Relative paths of every `info` file, e.g. `C0:FB:F9:60:1C:13/4C:87:5D:26:DC:9F/info`
+fn info_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for adapter in read_dir(dir).expect("readable bluetooth dir") {
+        let adapter = adapter.expect("readable adapter entry").path();
+        if !adapter.is_dir() {
+            continue;
+        }
+
+        for device in read_dir(&adapter).expect("readable adapter dir") {
+            let info = device.expect("readable device entry").path().join("info");
+            if info.exists() {
+                files.push(info.strip_prefix(dir).expect("inside dir").to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Copies info files and backdates them, so linux pairings look older than windows ones
+fn copy_tree(from: &Path, to: &Path) {
+    for file in info_files(from) {
+        create_dir_all(to.join(&file).parent().expect("device dir")).expect("create device dir");
+        copy(from.join(&file), to.join(&file)).expect("copy info file");
+        set_modified(&to.join(&file), UNIX_EPOCH + PAIRED_IN_LINUX_AT);
+    }
+}
+
+fn set_modified(path: &Path, time: SystemTime) {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|f| f.set_modified(time))
+        .expect("set modification time");
+}
+
+fn read_ini(path: &Path) -> Ini {
+    let content = read_to_string(path).expect("readable info file");
+    serde_ini::from_str(&content).expect("valid info file")
+}
+
+/// Compares every info file in `bt_dir` with the `expected` ones of `fixture`
+fn assert_golden(fixture: &Path, bt_dir: &Path) {
+    let expected_dir = fixture.join("expected");
+    assert_eq!(info_files(&expected_dir), info_files(bt_dir));
+
+    for file in info_files(&expected_dir) {
+        assert_eq!(
+            read_ini(&expected_dir.join(&file)),
+            read_ini(&bt_dir.join(&file)),
+            "{} differs from golden file",
+            file.display()
+        );
+    }
+}
+
+/// Asserts every `before` info file of `fixture` is still in `bt_dir` as it was
+fn assert_untouched(fixture: &Path, bt_dir: &Path) {
+    for file in info_files(&fixture.join("before")) {
+        assert_eq!(
+            read_to_string(fixture.join("before").join(&file)).expect("readable info file"),
+            read_to_string(bt_dir.join(&file)).expect("untouched info file"),
+            "{} was changed",
+            file.display()
+        );
+    }
+}
+
+/// bt-dualboot-rs reading windows keys from `export`
+fn bt_dualboot(export: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_bt-dualboot-rs"));
+    command.arg("--reg-export").arg(export);
+    command
+}
+
+/// Linux installation holding the `before` tree of `fixture`
+fn fixture_root(fixture: &Path) -> TempDir {
+    let root = tempfile::tempdir().expect("temp dir");
+    copy_tree(&fixture.join("before"), &root.path().join(LINUX_BT_DIR));
+    root
+}
+
+/// Runs `sync --yes` with windows keys from `export` into the installation at `root`
+fn sync(export: &Path, root: &Path, args: &[&str]) -> Output {
+    bt_dualboot(export)
+        .args(["sync", "--yes"])
+        .args(args)
+        .arg("--root")
+        .arg(root)
+        .output()
+        .expect("run bt-dualboot-rs")
+}
+
+/// Syncs the windows keys of `fixture` into a fresh copy of its `before` tree
+fn run_sync(fixture: &Path, args: &[&str]) -> (Output, TempDir) {
+    let root = fixture_root(fixture);
+    let output = sync(&fixture.join("export.reg"), root.path(), args);
+    (output, root)
+}
+
+fn run_case(case: &str) {
+    let fixture = fixture_dir(case);
+    let extra_args = read_to_string(fixture.join("args")).unwrap_or_default();
+    let extra_args: Vec<_> = extra_args.lines().collect();
+
+    let (output, root) = run_sync(&fixture, &extra_args);
+    let bt_dir = root.path().join(LINUX_BT_DIR);
+    assert!(
+        output.status.success(),
+        "sync failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_golden(&fixture, &bt_dir);
+
+    let backups: Vec<_> = read_dir(root.path().join(LINUX_BACKUP_DIR))
+        .expect("backups are made")
+        .map(|b| b.expect("readable backup entry").path())
+        .collect();
+    assert_eq!(backups.len(), 1, "one backup per run");
+    for file in info_files(&fixture.join("before")) {
+        assert_eq!(
+            read_to_string(fixture.join("before").join(&file)).expect("readable info file"),
+            read_to_string(backups[0].join(&file)).expect("backed up info file"),
+        );
+    }
+}
+
+#[test]
+fn classic() {
+    run_case("classic");
+}
+
+#[test]
+fn le_legacy() {
+    run_case("le-legacy");
+}
+
+#[test]
+fn le_secure_connections() {
+    run_case("le-secure-connections");
+}
+
+#[test]
+fn dual_mode() {
+    run_case("dual-mode");
+}
+
+#[test]
+fn le_repaired_with_secure_connections() {
+    run_case("le-repaired-with-secure-connections");
+}
+
+#[test]
+fn refuses_without_yes_when_not_a_terminal() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+
+    let output = bt_dualboot(&fixture.join("export.reg"))
+        .args(["sync", "--root"])
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert_eq!(output.status.code(), Some(3), "nothing to do");
+
+    assert_untouched(&fixture, &root.path().join(LINUX_BT_DIR));
+    assert!(!root.path().join(LINUX_BACKUP_DIR).exists());
+}
+
+#[test]
+fn second_run_has_nothing_to_do() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+
+    let sync = || sync(&fixture.join("export.reg"), root.path(), &["--output", "json"]);
+    assert_eq!(sync().status.code(), Some(0), "first run updates");
+
+    let output = sync();
+    assert_eq!(output.status.code(), Some(3), "second run has nothing to do");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("json report");
+    let results: Vec<_> = report["roots"][0]["devices"]
+        .as_array()
+        .expect("devices")
+        .iter()
+        .map(|d| (d["address"].as_str(), d["result"].as_str()))
+        .collect();
+    assert!(results.contains(&(Some("4C:87:5D:26:DC:9F"), Some("unchanged"))));
+    assert!(results.contains(&(Some("00:1A:7D:DA:71:13"), Some("skipped"))));
+    assert!(report["roots"][0]["backup_dir"].is_null());
+}
+
+#[test]
+fn ledger_records_changes() {
+    let (output, root) = run_sync(&fixture_dir("classic"), &[]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let ledger = read_to_string(root.path().join(LINUX_LEDGER_PATH)).expect("ledger is written");
+    let entries: Vec<serde_json::Value> = ledger
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json line"))
+        .collect();
+    assert_eq!(entries.len(), 1, "one entry per updated device");
+    assert_eq!(entries[0]["direction"], "windows-to-linux");
+    assert_eq!(entries[0]["device"], "4C:87:5D:26:DC:9F");
+    assert_eq!(entries[0]["sections"][0]["section"], "LinkKey");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bt-dualboot-rs"))
+        .arg("history")
+        .arg("--root")
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("4C:87:5D:26:DC:9F"));
+}
+
+#[test]
+fn keeps_newer_linux_pairing_unless_forced() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+    let bt_dir = root.path().join(LINUX_BT_DIR);
+
+    // re-paired in linux after windows last connected
+    let info = Path::new("C0:FB:F9:60:1C:13/4C:87:5D:26:DC:9F/info");
+    set_modified(&bt_dir.join(info), SystemTime::now());
+
+    let export = fixture.join("export.reg");
+    assert_eq!(sync(&export, root.path(), &[]).status.code(), Some(3), "nothing to do");
+    assert_untouched(&fixture, &bt_dir);
+
+    let output = sync(&export, root.path(), &["--force"]);
+    assert_eq!(output.status.code(), Some(0), "forced update");
+    assert_eq!(
+        read_ini(&fixture.join("expected").join(info))["LinkKey"],
+        read_ini(&bt_dir.join(info))["LinkKey"]
+    );
+}
+
+#[test]
+fn yes_answers_conflict_prompts() {
+    let fixture = fixture_dir("classic");
+    let (output, root) = run_sync(&fixture, &["--conflict", "ask"]);
+    assert_eq!(output.status.code(), Some(0), "overwritten without a prompt");
+    let info = "C0:FB:F9:60:1C:13/4C:87:5D:26:DC:9F/info";
+    assert_eq!(
+        read_ini(&fixture.join("expected").join(info))["LinkKey"],
+        read_ini(&root.path().join(LINUX_BT_DIR).join(info))["LinkKey"]
+    );
+}
+
+#[test]
+fn names_devices_from_bluez_cache() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+
+    // seen in linux but never paired there
+    let cache_dir = root.path().join(LINUX_BT_DIR).join("C0:FB:F9:60:1C:13/cache");
+    create_dir_all(&cache_dir).expect("create cache dir");
+    std::fs::write(
+        cache_dir.join("00:1A:7D:DA:71:13"),
+        "[General]\nName=Keychron K2\n\n[ServiceRecords]\n",
+    )
+    .expect("write cache file");
+
+    let output = sync(&fixture.join("export.reg"), root.path(), &["--output", "json"]);
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("json report");
+    let skipped = report["roots"][0]["devices"]
+        .as_array()
+        .expect("devices")
+        .iter()
+        .find(|d| d["address"] == "00:1A:7D:DA:71:13")
+        .expect("windows only device is reported");
+    assert_eq!(skipped["name"], "Keychron K2");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Keychron K2 (00:1A:7D:DA:71:13)"));
+}
+
+#[test]
+fn linux_source_refuses_saved_export() {
+    let fixture = fixture_dir("classic");
+    let root = tempfile::tempdir().expect("temp dir");
+    copy_tree(&fixture.join("expected"), &root.path().join(LINUX_BT_DIR));
+
+    let output = sync(&fixture.join("export.reg"), root.path(), &["--source", "linux"]);
+    assert_eq!(output.status.code(), Some(1), "nothing to write into");
+    assert!(!root.path().join(LINUX_BACKUP_DIR).exists());
+    assert!(!root.path().join(LINUX_LEDGER_PATH).exists());
+}
+
+#[test]
+fn refuses_le_keys_for_another_address_type() {
+    let fixture = fixture_dir("le-legacy");
+    let root = fixture_root(&fixture);
+
+    // paired with a static random address in windows, a public one in linux
+    let export = root.path().join("export.reg");
+    let export_str = read_to_string(fixture.join("export.reg")).expect("readable export");
+    std::fs::write(
+        &export,
+        export_str.replace("\"AddressType\"=dword:00000000", "\"AddressType\"=dword:00000001"),
+    )
+    .expect("writable export");
+
+    let output = sync(&export, root.path(), &["--output", "json", "--conflict", "prefer-target"]);
+    assert_eq!(output.status.code(), Some(3), "kept, the keys aren't written");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("linux keys are preferred"));
+
+    let output = sync(&export, root.path(), &["--output", "json"]);
+    assert_eq!(output.status.code(), Some(1), "every device failed");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("address type is public in linux but static"));
+
+    assert_untouched(&fixture, &root.path().join(LINUX_BT_DIR));
+}
+
+#[test]
+fn replaces_local_irk_of_adapter() {
+    let fixture = fixture_dir("le-legacy");
+    let linux_identity = "[General]\nIdentityResolvingKey=0F1E2D3C4B5A69788796A5B4C3D2E1F0\n";
+    let with_identity = || {
+        let root = fixture_root(&fixture);
+        let identity = root.path().join(LINUX_BT_DIR).join("C0:FB:F9:60:1C:13/identity");
+        std::fs::write(&identity, linux_identity).expect("write identity file");
+
+        let export = root.path().join("export.reg");
+        let export_str = read_to_string(fixture.join("export.reg")).expect("readable export");
+        std::fs::write(
+            &export,
+            export_str.replace(
+                "Keys\\c0fbf9601c13]\n",
+                "Keys\\c0fbf9601c13]\n\"IRK\"=hex:9e,0c,27,a4,f5,e0,b5,c8,d2,c1,a9,d8,4e,3f,1b,67\n",
+            ),
+        )
+        .expect("writable export");
+        (root, identity, export)
+    };
+
+    // the LE pairing of linux is kept, so is the IRK it was made with
+    let (root, identity, export) = with_identity();
+    let config = root.path().join("bt-dualboot.conf");
+    std::fs::write(&config, "[Conflicts]\nPolicy=prefer-target\n").expect("write config");
+    let output = bt_dualboot(&export)
+        .arg("--config")
+        .arg(&config)
+        .args(["sync", "--yes", "--root"])
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert_eq!(output.status.code(), Some(3), "nothing to do");
+    assert_eq!(read_to_string(&identity).expect("kept identity"), linux_identity);
+
+    let (root, identity, export) = with_identity();
+    let output = sync(&export, root.path(), &["--output", "json"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("json report");
+    assert_eq!(
+        report["roots"][0]["identities"],
+        serde_json::json!(["C0:FB:F9:60:1C:13"])
+    );
+    assert_eq!(
+        read_ini(&identity)["General"]["IdentityResolvingKey"],
+        "9E0C27A4F5E0B5C8D2C1A9D84E3F1B67"
+    );
+
+    let backup_dir = PathBuf::from(report["roots"][0]["backup_dir"].as_str().expect("backed up"));
+    assert_eq!(
+        read_to_string(backup_dir.join("C0:FB:F9:60:1C:13/identity")).expect("backed up identity"),
+        linux_identity
+    );
+
+    let ledger = read_to_string(root.path().join(LINUX_LEDGER_PATH)).expect("ledger is written");
+    let entry: serde_json::Value = ledger
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json line"))
+        .find(|e: &serde_json::Value| e["device"] == "C0:FB:F9:60:1C:13")
+        .expect("identity in the ledger");
+    assert_eq!(entry["adapter"], "C0:FB:F9:60:1C:13");
+    assert_eq!(entry["sections"][0]["section"], "IdentityResolvingKey");
+    assert_ne!(entry["sections"][0]["before"], entry["sections"][0]["after"]);
+}
+
+#[test]
+fn syncs_from_exported_keys() {
+    let fixture = fixture_dir("dual-mode");
+    let root = fixture_root(&fixture);
+
+    let keys = root.path().join("keys.json");
+    let output = bt_dualboot(&fixture.join("export.reg"))
+        .arg("export")
+        .arg(&keys)
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert!(
+        output.status.success(),
+        "export failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bt-dualboot-rs"))
+        .args(["sync", "--yes", "--source", "json", "--input"])
+        .arg(&keys)
+        .arg("--root")
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert!(
+        output.status.success(),
+        "sync failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_golden(&fixture, &root.path().join(LINUX_BT_DIR));
+
+    let ledger = read_to_string(root.path().join(LINUX_LEDGER_PATH)).expect("ledger is written");
+    assert!(ledger.contains(r#""direction":"file-to-linux""#));
+}
+
+#[test]
+fn writes_exported_keys_into_windows() {
+    let fixture = fixture_dir("dual-mode");
+    let root = fixture_root(&fixture);
+    let keys = root.path().join("keys.json");
+    let export = fixture.join("export.reg");
+    let output = bt_dualboot(&export)
+        .arg("export")
+        .arg(&keys)
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert!(output.status.success());
+
+    let output = bt_dualboot(&export)
+        .args(["sync", "--yes", "--source", "json", "--target", "windows", "--input"])
+        .arg(&keys)
+        .arg("--root")
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert_eq!(output.status.code(), Some(1), "nothing to write into");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't be written into a saved export"));
+
+    let output = sync(&export, root.path(), &["--target", "windows"]);
+    assert_eq!(output.status.code(), Some(1), "same system");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("same system"));
+    assert_untouched(&fixture, &root.path().join(LINUX_BT_DIR));
+}
+
+#[test]
+fn verify_reports_drift_after_sync() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+    let bt_dir = root.path().join(LINUX_BT_DIR);
+    let set_mode = |path: &Path, mode: u32| {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).expect("set mode")
+    };
+    for file in info_files(&bt_dir) {
+        set_mode(&bt_dir.join(&file), 0o600);
+        set_mode(bt_dir.join(&file).parent().expect("device dir"), 0o700);
+        set_mode(
+            bt_dir.join(&file).parent().and_then(Path::parent).expect("adapter dir"),
+            0o700,
+        );
+    }
+
+    let verify = || {
+        bt_dualboot(&fixture.join("export.reg"))
+            .args(["verify", "--root"])
+            .arg(root.path())
+            .output()
+            .expect("run bt-dualboot-rs")
+    };
+    let output = verify();
+    assert_eq!(output.status.code(), Some(1), "not synced yet");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no sync from windows"));
+
+    let output = sync(&fixture.join("export.reg"), root.path(), &[]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = verify();
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("verified"));
+
+    let info = bt_dir.join(&info_files(&bt_dir)[0]);
+    set_mode(&info, 0o644);
+    let output = verify();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("has mode 644, expected 600"));
+
+    std::fs::write(&info, "not an info file").expect("writable info file");
+    let output = verify();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("info doesn't parse"));
+}
+
+#[test]
+fn runs_hooks_around_sync() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+
+    let config = root.path().join("bt-dualboot.conf");
+    let sync = || {
+        bt_dualboot(&fixture.join("export.reg"))
+            .arg("--config")
+            .arg(&config)
+            .args(["sync", "--yes", "--root"])
+            .arg(root.path())
+            .output()
+            .expect("run bt-dualboot-rs")
+    };
+
+    std::fs::write(&config, "[Hooks]\nPreSync=echo stopping && exit 3\n").expect("write config");
+    let output = sync();
+    assert_eq!(output.status.code(), Some(1), "failing pre_sync aborts");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pre_sync: stopping"));
+    assert_untouched(&fixture, &root.path().join(LINUX_BT_DIR));
+
+    let env_file = root.path().join("env");
+    let summary_file = root.path().join("summary.json");
+    std::fs::write(
+        &config,
+        format!(
+            "[Hooks]\nPreSync=true\nPostSync=env | grep ^BT_DUALBOOT_ > {} && \
+            stat -c %a \"$BT_DUALBOOT_SUMMARY\" >> {0} && cp \"$BT_DUALBOOT_SUMMARY\" {}\n",
+            env_file.display(),
+            summary_file.display()
+        ),
+    )
+    .expect("write config");
+    assert_eq!(sync().status.code(), Some(0));
+
+    let env = read_to_string(&env_file).expect("post_sync ran");
+    assert!(env.contains("BT_DUALBOOT_HOOK=post_sync\n"));
+    assert!(env.contains("BT_DUALBOOT_DIRECTION=windows-to-linux\n"));
+    assert!(env.contains("BT_DUALBOOT_EXIT_CODE=0\n"));
+    assert!(env.contains("BT_DUALBOOT_ADAPTERS=C0:FB:F9:60:1C:13\n"));
+    let summary: serde_json::Value =
+        serde_json::from_str(&read_to_string(&summary_file).expect("summary copied"))
+            .expect("json summary");
+    assert_eq!(summary["roots"][0]["root"], root.path().display().to_string());
+    assert!(env.ends_with("\n600\n"), "summary is private: {}", env);
+
+    // the registry of a saved export can't be written, post_sync still runs
+    let output = bt_dualboot(&fixture.join("export.reg"))
+        .arg("--config")
+        .arg(&config)
+        .args(["sync", "--yes", "--source", "linux", "--root"])
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert_eq!(output.status.code(), Some(1));
+    let env = read_to_string(&env_file).expect("post_sync ran");
+    assert!(env.contains("BT_DUALBOOT_DIRECTION=linux-to-windows\n"));
+    assert!(env.contains("BT_DUALBOOT_EXIT_CODE=1\n"));
+    let summary: serde_json::Value =
+        serde_json::from_str(&read_to_string(&summary_file).expect("summary copied"))
+            .expect("json summary");
+    assert!(summary["errors"][0]["error"]
+        .as_str()
+        .is_some_and(|e| e.contains("saved export")));
+}
+
+#[test]
+fn tui_refuses_without_a_terminal() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+
+    let output = bt_dualboot(&fixture.join("export.reg"))
+        .args(["tui", "--root"])
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("needs a terminal"));
+}
+
+#[test]
+fn applies_conflict_policies() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+    let bt_dir = root.path().join(LINUX_BT_DIR);
+    let info = Path::new("C0:FB:F9:60:1C:13/4C:87:5D:26:DC:9F/info");
+
+    let config = root.path().join("bt-dualboot.conf");
+    let sync = |conflicts: &str, args: &[&str]| {
+        std::fs::write(&config, format!("[Conflicts]\n{}", conflicts)).expect("write config");
+        let output = bt_dualboot(&fixture.join("export.reg"))
+            .arg("--config")
+            .arg(&config)
+            .args(["sync", "--yes", "--output", "json", "--root"])
+            .arg(root.path())
+            .args(args)
+            .output()
+            .expect("run bt-dualboot-rs");
+        let report: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("json report");
+        let device = report["roots"][0]["devices"]
+            .as_array()
+            .expect("devices")
+            .iter()
+            .find(|d| d["address"] == "4C:87:5D:26:DC:9F")
+            .expect("device in report")
+            .clone();
+        (output.status.code(), device)
+    };
+
+    let (code, device) = sync("Policy=skip\n", &["--fill-metadata"]);
+    assert_eq!(code, Some(3), "nothing to do");
+    assert_eq!(device["result"], "skipped");
+    assert_eq!(device["reason"], "keys differ, left alone by the skip policy");
+    assert_eq!(device["policy"], "skip");
+    assert_untouched(&fixture, &bt_dir);
+
+    let (code, device) = sync("Policy=prefer-target\n", &[]);
+    assert_eq!(code, Some(3), "nothing to do");
+    assert_eq!(device["result"], "skipped");
+    assert_eq!(device["reason"], "linux keys are preferred");
+    assert_eq!(device["policy"], "prefer-target");
+    assert_untouched(&fixture, &bt_dir);
+
+    let (code, device) = sync(
+        "Policy=prefer-target\n4C:87:5D:26:DC:9F=prefer-source\n",
+        &["--conflict", "skip"],
+    );
+    assert_eq!(code, Some(0), "device policy beats the global one");
+    assert_eq!(device["result"], "updated");
+    assert_eq!(device["policy"], "prefer-source");
+    assert_eq!(
+        read_ini(&fixture.join("expected").join(info))["LinkKey"],
+        read_ini(&bt_dir.join(info))["LinkKey"]
+    );
+
+    // same keys, skipped devices don't get metadata either
+    let synced = read_to_string(bt_dir.join(info))
+        .expect("readable info file")
+        .replace("Name=WH-1000XM4", "Name=Headphones");
+    std::fs::write(bt_dir.join(info), &synced).expect("writable info file");
+    let (code, device) = sync("Policy=skip\n", &["--fill-metadata"]);
+    assert_eq!(code, Some(3), "nothing to do");
+    assert_eq!(device["result"], "unchanged");
+    assert_eq!(read_to_string(bt_dir.join(info)).expect("readable info file"), synced);
+}
+
+#[test]
+fn logs_device_fields_to_file() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+
+    let log_file = root.path().join("bt-dualboot.log");
+    let output = bt_dualboot(&fixture.join("export.reg"))
+        .arg("--log-file")
+        .arg(&log_file)
+        .args(["--quiet", "sync", "--yes", "--root"])
+        .arg(root.path())
+        .output()
+        .expect("run bt-dualboot-rs");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        output.stderr.is_empty(),
+        "only errors with --quiet: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let log = read_to_string(&log_file).expect("log file is written");
+    assert!(log.lines().any(|l| l.contains(" INFO ")
+        && l.ends_with(" DEVICE=4C:87:5D:26:DC:9F ADAPTER=C0:FB:F9:60:1C:13 ACTION=updated")));
+    assert!(log.lines().any(|l| l.contains(" WARN ")
+        && l.ends_with(" DEVICE=00:1A:7D:DA:71:13 ADAPTER=C0:FB:F9:60:1C:13 ACTION=skipped")));
+}
+
+#[test]
+fn reads_as_user_and_refuses_to_write() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // As root the commands are run as nobody, the binary and the export are copied out
+    // of the checkout, which nobody may not be able to enter
+    let status = read_to_string("/proc/self/status").expect("process status");
+    let is_root = status
+        .lines()
+        .find_map(|l| l.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().nth(1))
+        == Some("0");
+    assert!(
+        !is_root || Command::new("setpriv").arg("--version").output().is_ok(),
+        "running as root needs setpriv to drop the privileges"
+    );
+
+    let fixture = fixture_dir("classic");
+    let root = tempfile::tempdir().expect("temp dir");
+    let set_mode = |path: &Path, mode: u32| {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).expect("set mode")
+    };
+    set_mode(root.path(), 0o755);
+    let bt_dir = root.path().join(LINUX_BT_DIR);
+    copy_tree(&fixture.join("before"), &bt_dir);
+    set_mode(&bt_dir, 0o500);
+    let binary = root.path().join("bt-dualboot-rs");
+    copy(env!("CARGO_BIN_EXE_bt-dualboot-rs"), &binary).expect("copy binary");
+    let export = root.path().join("export.reg");
+    copy(fixture.join("export.reg"), &export).expect("copy export");
+    set_mode(&export, 0o644);
+
+    let run = |args: &[&str]| {
+        let mut command = if is_root {
+            let mut command = Command::new("setpriv");
+            command.args(["--reuid=65534", "--regid=65534", "--clear-groups"]);
+            command.arg(&binary);
+            command
+        } else {
+            Command::new(&binary)
+        };
+        command
+            .arg("--reg-export")
+            .arg(&export)
+            .args(args)
+            .arg("--root")
+            .arg(root.path())
+            .output()
+            .expect("run bt-dualboot-rs")
+    };
+
+    let output = run(&["export", "--source", "windows"]);
+    assert_eq!(output.status.code(), Some(0), "export only reads the export");
+    let keys: serde_json::Value = serde_json::from_slice(&output.stdout).expect("json keys");
+    let devices = keys["devices"].as_array().expect("devices");
+    assert!(devices.iter().any(|d| d["address"] == "4C:87:5D:26:DC:9F"));
+
+    let output = run(&["sync", "--yes", "--non-interactive"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&bt_dir.display().to_string()));
+    assert!(stderr.contains("needs root, run it with sudo or pass --elevate"));
+    for file in info_files(&fixture.join("before")) {
+        assert_eq!(
+            read_ini(&fixture.join("before").join(&file)),
+            read_ini(&bt_dir.join(&file)),
+            "nothing is written"
+        );
+    }
+
+    set_mode(&bt_dir, 0o555);
+    let output = run(&["tui"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("needs a terminal"), "browsing only reads: {}", stderr);
+}
+
+#[test]
+fn skips_malformed_device_metadata() {
+    let fixture = fixture_dir("classic");
+    let root = fixture_root(&fixture);
+    let export = root.path().join("export.reg");
+    let export_str = read_to_string(fixture.join("export.reg")).expect("readable export");
+    std::fs::write(
+        &export,
+        export_str.replace("\"COD\"=dword:00240404", "\"COD\"=hex:04,04,24,00"),
+    )
+    .expect("writable export");
+
+    let output = sync(&export, root.path(), &[]);
+    assert_eq!(output.status.code(), Some(0), "keys are synced anyway");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("skipped metadata of device 4c875d26dc9f: COD is not a dword"));
+    let info = "C0:FB:F9:60:1C:13/4C:87:5D:26:DC:9F/info";
+    assert_eq!(
+        read_ini(&fixture.join("expected").join(info))["LinkKey"],
+        read_ini(&root.path().join(LINUX_BT_DIR).join(info))["LinkKey"]
+    );
+}